@@ -2,50 +2,152 @@ use std::env;
 use std::ffi::OsStr;
 use std::fs::File;
 use std::io;
-use std::io::Read;
+use std::io::{BufWriter, Read, Write};
 use std::path::Path;
+use std::process;
 
-use svfmt::{self, format, parse};
+use svfmt::{self, parse, Config, Encoding, Session};
 
 use env_logger;
 use snafu::ErrorCompat;
 
+struct Options {
+    encoding: Option<Encoding>,
+    keep_byte_order_mark: bool,
+    verify_idempotent: bool,
+    debug_tree: bool,
+}
+
 fn main() {
     env_logger::init();
 
-    let filename = env::args().skip(1).next().unwrap();
-    let filename = Path::new(&filename);
-    let extension = filename.extension().and_then(OsStr::to_str).unwrap();
+    let mut filenames = Vec::new();
+    let mut options = Options {
+        encoding: None,
+        keep_byte_order_mark: true,
+        verify_idempotent: false,
+        debug_tree: false,
+    };
 
-    let source = load_file(filename).unwrap();
+    for arg in env::args().skip(1) {
+        if let Some(name) = arg.strip_prefix("--encoding=") {
+            match name.parse::<Encoding>() {
+                Ok(encoding) => options.encoding = Some(encoding),
+                Err(message) => {
+                    eprintln!("error: {}", message);
+                    process::exit(2);
+                }
+            }
+        } else if arg == "--remove-byte-order-mark" {
+            options.keep_byte_order_mark = false;
+        } else if arg == "--verify-idempotent" {
+            options.verify_idempotent = true;
+        } else if arg == "--debug-tree" {
+            options.debug_tree = true;
+        } else {
+            filenames.push(arg);
+        }
+    }
 
-    match transform(&extension, &source) {
-        Ok(_) => {}
+    let config = Config::default()
+        .encoding(options.encoding)
+        .keep_byte_order_mark(options.keep_byte_order_mark);
+    let mut verilog = match Session::new(config.clone()) {
+        Ok(session) => session,
         Err(e) => {
-            eprintln!("An error occurred: {}", e);
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }
+    };
+    // Only created when a C file is given
+    let mut c = None;
+
+    let mut success = true;
+    for filename in &filenames {
+        let filename = Path::new(filename);
+        let extension = filename.extension().and_then(OsStr::to_str).unwrap();
+        let session = match extension {
+            "c" | "h" => c.get_or_insert_with(|| {
+                Session::with_language(unsafe { svfmt::tree_sitter_c() }, config.clone()).unwrap()
+            }),
+            _ => &mut verilog,
+        };
+
+        success &= process_file(filename, session, &options);
+    }
+
+    if !success {
+        process::exit(1);
+    }
+}
+
+/// Formats a file, or verifies that formatting it is idempotent, and reports any errors
+///
+/// Returns false if there were errors.
+fn process_file(filename: &Path, session: &mut Session, options: &Options) -> bool {
+    let bytes = load_file(filename).unwrap();
+    let source = match svfmt::decode_source(&bytes, session.config()) {
+        Ok((_, source)) => source,
+        Err(e) => {
+            eprintln!("error: {}: {}", filename.display(), e);
+            return false;
+        }
+    };
+
+    let result = if options.debug_tree {
+        debug_tree(session, &source).map(|_| true)
+    } else if options.verify_idempotent {
+        verify(filename, session, &source)
+    } else {
+        transform(session, &bytes).map(|_| true)
+    };
+
+    match result {
+        Ok(success) => success,
+        Err(e) => {
+            let e = e.with_path(filename);
+            eprint!("{}", e.render(&source));
             if let Some(backtrace) = ErrorCompat::backtrace(&e) {
                 println!("{}", backtrace);
             }
+            false
         }
     }
 }
 
-fn transform(extension: &str, source: &str) -> svfmt::Result<()> {
-    let language = match extension {
-        "c" | "h" => unsafe { svfmt::tree_sitter_c() },
-        _ => unsafe { svfmt::tree_sitter_verilog() },
-    };
+/// Reports the file if formatting it a second time changes the output
+fn verify(filename: &Path, session: &mut Session, source: &str) -> svfmt::Result<bool> {
+    match session.verify_idempotent(source)? {
+        Some(instability) => {
+            eprintln!("{}: formatting is not idempotent", filename.display());
+            eprint!("{}", instability);
+            Ok(false)
+        }
+        None => Ok(true),
+    }
+}
+
+/// Writes the formatted file to stdout as it is formatted
+fn transform(session: &mut Session, bytes: &[u8]) -> svfmt::Result<()> {
+    let stdout = io::stdout();
+    let mut output = BufWriter::new(stdout.lock());
+
+    session.format_bytes_to(&mut output, bytes)?;
+    output.flush()?;
+    Ok(())
+}
 
-    let tree = parse(language, &source)?;
-    svfmt::debug(&mut std::io::stdout(), &source, &tree)?;
-    format(&mut std::io::stdout(), &source, &tree)
+/// Writes the syntax tree of the file to stdout instead of formatting it
+fn debug_tree(session: &Session, source: &str) -> svfmt::Result<()> {
+    let tree = parse(session.language(), source)?;
+    svfmt::debug(&mut io::stdout(), source, &tree)
 }
 
-fn load_file(path: &Path) -> io::Result<String> {
+fn load_file(path: &Path) -> io::Result<Vec<u8>> {
     let mut file = File::open(path)?;
 
-    let mut content = String::new();
-    file.read_to_string(&mut content)?;
+    let mut content = Vec::new();
+    file.read_to_end(&mut content)?;
 
     Ok(content)
 }
@@ -1,94 +1,135 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::env;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Write};
+use std::io::{self, BufReader, Write};
 use std::path::{Path, PathBuf};
 
+use serde::Deserialize;
+
 fn main() {
     build_language("verilog");
     build_language("c");
 
-    generate_symbols_enum().unwrap();
-}
+    let node_types = parse_node_types().unwrap();
 
-struct Symbol {
-    name: String,
-    id: String,
+    generate_symbols_enum(&node_types).unwrap();
+    write_syntax_wrappers(&node_types, out_file("syntax.rs")).unwrap();
 }
 
-fn in_file() -> PathBuf {
-    ["..", "vendor", "tree-sitter-verilog", "src", "parser.c"]
-        .iter()
-        .collect()
+/// A node type from the grammar's node-types.json
+#[derive(Deserialize)]
+struct NodeType {
+    #[serde(rename = "type")]
+    kind: String,
+    named: bool,
+    #[serde(default)]
+    fields: BTreeMap<String, ChildTypes>,
+    children: Option<ChildTypes>,
+    #[serde(default)]
+    subtypes: Vec<TypeRef>,
 }
 
-fn out_file() -> PathBuf {
-    let out_dir = env::var("OUT_DIR").unwrap();
-    Path::new(&out_dir).join("symbols.rs")
+#[derive(Deserialize)]
+struct ChildTypes {
+    types: Vec<TypeRef>,
 }
 
-fn generate_symbols_enum() -> io::Result<()> {
-    let symbols = parse_symbols(in_file())?;
+#[derive(Deserialize)]
+struct TypeRef {
+    #[serde(rename = "type")]
+    kind: String,
+    named: bool,
+}
 
-    write_symbols_enum(&symbols, out_file())
+impl TypeRef {
+    /// Indicates whether the type has a wrapper
+    ///
+    /// Anonymous nodes (e.g. keywords and punctuation) and hidden nodes don't.
+    fn is_wrapped(&self) -> bool {
+        self.named && !self.kind.starts_with('_')
+    }
 }
 
-fn parse_symbols(path: PathBuf) -> io::Result<Vec<Symbol>> {
-    let mut symbols = Vec::new();
+fn node_types_file() -> PathBuf {
+    [
+        "..",
+        "vendor",
+        "tree-sitter-verilog",
+        "src",
+        "node-types.json",
+    ]
+    .iter()
+    .collect()
+}
 
-    let f = File::open(path)?;
-    let reader = BufReader::new(f);
+fn out_file(name: &str) -> PathBuf {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    Path::new(&out_dir).join(name)
+}
 
-    for line in reader.lines() {
-        if let Ok(line) = line {
-            add_symbol_if_starts_with(&line, "sym_", &mut symbols);
-            add_symbol_if_starts_with(&line, "alias_sym_", &mut symbols);
-        }
-    }
+fn parse_node_types() -> io::Result<Vec<NodeType>> {
+    let path = node_types_file();
+    println!("cargo:rerun-if-changed={}", path.to_string_lossy());
 
-    Ok(symbols)
-}
-
-fn add_symbol_if_starts_with(line: &str, starts_with: &str, symbols: &mut Vec<Symbol>) {
-    // Ignore hidden symbols
-    //
-    // Hidden symbols begin with a '_'.
-    let not_starts_with = format!("{}_", starts_with);
-
-    let line = line.trim_start();
-
-    if line.starts_with(starts_with) && !line.starts_with(&not_starts_with) {
-        let tokens = line.split(|c| char::is_whitespace(c) || c == '=' || c == ',')
-            .filter(|s| !s.is_empty())
-            .collect::<Vec<&str>>();
-        let (name, id) = (tokens[0], tokens[1].to_string());
-        let name = name
-            .get(starts_with.len()..)
-            .unwrap().split('_')
-            .map(titlecase)
-            .collect::<Vec<String>>()
-            .join("");
-        symbols.push(Symbol { name, id });
-    }
+    let node_types = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    Ok(node_types)
 }
 
-fn write_symbols_enum(symbols: &[Symbol], path: PathBuf) -> io::Result<()> {
-    let mut f = File::create(path)?;
+/// Generates the `Symbol` enum
+///
+/// Only names are generated.  Numeric ids are looked up from the `Language` at runtime.  See
+/// src/kinds.rs.  Fields have no enum.  The typed wrappers look children up by field name.
+fn generate_symbols_enum(node_types: &[NodeType]) -> io::Result<()> {
+    let symbols = node_types
+        .iter()
+        .filter(|node_type| node_type.named && !node_type.kind.starts_with('_'))
+        .map(|node_type| node_type.kind.as_str())
+        .collect::<BTreeSet<_>>();
 
-    writeln!(f, "#[derive(Debug, PartialEq)]")?;
-    writeln!(f, "enum Symbol {{")?;
+    check_type_names(&symbols);
+
+    let mut f = File::create(out_file("symbols.rs"))?;
+    write_symbols_enum(&mut f, "Symbol", &symbols)
+}
+
+fn write_symbols_enum(f: &mut File, enum_name: &str, names: &BTreeSet<&str>) -> io::Result<()> {
+    writeln!(f, "#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]")?;
+    writeln!(f, "enum {} {{", enum_name)?;
     writeln!(f, "    Undefined,")?;
-    for symbol in symbols {
-        writeln!(f, "    {},", symbol.name)?;
+    for name in names {
+        writeln!(f, "    {},", type_name(name))?;
     }
     writeln!(f, "}}")?;
     writeln!(f)?;
-    writeln!(f, "impl From<u16> for Symbol {{")?;
-    writeln!(f, "    fn from(id: u16) -> Self {{")?;
-    writeln!(f, "        match id {{")?;
-    for symbol in symbols {
-        writeln!(f, "            {} => Symbol::{},", symbol.id, symbol.name)?;
+    writeln!(f, "impl {} {{", enum_name)?;
+    writeln!(f, "    /// Returns the name used by the grammar")?;
+    writeln!(f, "    fn name(self) -> &'static str {{")?;
+    writeln!(f, "        match self {{")?;
+    for name in names {
+        writeln!(
+            f,
+            "            {}::{} => {:?},",
+            enum_name,
+            type_name(name),
+            name
+        )?;
+    }
+    writeln!(f, "            {}::Undefined => \"\",", enum_name)?;
+    writeln!(f, "        }}")?;
+    writeln!(f, "    }}")?;
+    writeln!(f)?;
+    writeln!(f, "    fn from_name(name: &str) -> Self {{")?;
+    writeln!(f, "        match name {{")?;
+    for name in names {
+        writeln!(
+            f,
+            "            {:?} => {}::{},",
+            name,
+            enum_name,
+            type_name(name)
+        )?;
     }
-    writeln!(f, "            _ => Symbol::Undefined,")?;
+    writeln!(f, "            _ => {}::Undefined,", enum_name)?;
     writeln!(f, "        }}")?;
     writeln!(f, "    }}")?;
     writeln!(f, "}}")?;
@@ -96,6 +137,186 @@ fn write_symbols_enum(symbols: &[Symbol], path: PathBuf) -> io::Result<()> {
     Ok(())
 }
 
+fn write_syntax_wrappers(node_types: &[NodeType], path: PathBuf) -> io::Result<()> {
+    let mut f = File::create(path)?;
+
+    let node_types = node_types
+        .iter()
+        .filter(|node_type| node_type.named && !node_type.kind.starts_with('_'));
+
+    for node_type in node_types {
+        let name = type_name(&node_type.kind);
+
+        writeln!(f, "/// Typed wrapper for `{}` nodes", node_type.kind)?;
+        writeln!(f, "#[derive(Debug, Clone, Copy)]")?;
+        writeln!(f, "pub(crate) struct {}<'a>(Node<'a>);", name)?;
+        writeln!(f)?;
+        writeln!(f, "impl<'a> Syntax<'a> for {}<'a> {{", name)?;
+        writeln!(f, "    const KIND: &'static str = {:?};", node_type.kind)?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "    fn cast(node: Node<'a>) -> ::std::option::Option<Self> {{"
+        )?;
+        if node_type.subtypes.is_empty() {
+            writeln!(f, "        if node.kind() == Self::KIND {{")?;
+        } else {
+            let kinds = node_type
+                .subtypes
+                .iter()
+                .map(|subtype| format!("{:?}", subtype.kind))
+                .collect::<Vec<_>>();
+            writeln!(f, "        if let {} = node.kind() {{", kinds.join(" | "))?;
+        }
+        writeln!(f, "            ::std::option::Option::Some({}(node))", name)?;
+        writeln!(f, "        }} else {{")?;
+        writeln!(f, "            ::std::option::Option::None")?;
+        writeln!(f, "        }}")?;
+        writeln!(f, "    }}")?;
+        writeln!(f)?;
+        writeln!(f, "    fn node(&self) -> Node<'a> {{")?;
+        writeln!(f, "        self.0")?;
+        writeln!(f, "    }}")?;
+        writeln!(f, "}}")?;
+        writeln!(f)?;
+        writeln!(f, "impl<'a> {}<'a> {{", name)?;
+
+        // Fields and child kinds may share a name.  Fields take precedence.
+        let mut methods = HashSet::new();
+
+        for (field, child_types) in &node_type.fields {
+            let method = method_name(field);
+            let wrapped = child_types
+                .types
+                .iter()
+                .filter(|child_type| child_type.is_wrapped())
+                .collect::<Vec<_>>();
+
+            if wrapped.len() == 1 && child_types.types.len() == 1 {
+                let child_name = type_name(&wrapped[0].kind);
+                writeln!(
+                    f,
+                    "    pub(crate) fn {}(&self) -> ::std::option::Option<{}<'a>> {{",
+                    method, child_name
+                )?;
+                writeln!(
+                    f,
+                    "        self.0.child_by_field_name({:?}).and_then({}::cast)",
+                    field, child_name
+                )?;
+            } else {
+                writeln!(
+                    f,
+                    "    pub(crate) fn {}(&self) -> ::std::option::Option<Node<'a>> {{",
+                    method
+                )?;
+                writeln!(f, "        self.0.child_by_field_name({:?})", field)?;
+            }
+            writeln!(f, "    }}")?;
+            writeln!(f)?;
+
+            methods.insert(method);
+        }
+
+        let child_types = node_type
+            .children
+            .iter()
+            .flat_map(|children| children.types.iter())
+            .filter(|child_type| child_type.is_wrapped());
+
+        for child_type in child_types {
+            let method = method_name(&child_type.kind);
+            let list_method = format!("{}_list", child_type.kind);
+            let child_name = type_name(&child_type.kind);
+
+            if !methods.contains(&method) {
+                writeln!(f, "    /// Returns the first `{}` child", child_type.kind)?;
+                writeln!(
+                    f,
+                    "    pub(crate) fn {}(&self) -> ::std::option::Option<{}<'a>> {{",
+                    method, child_name
+                )?;
+                writeln!(
+                    f,
+                    "        self.0.children().filter_map({}::cast).next()",
+                    child_name
+                )?;
+                writeln!(f, "    }}")?;
+                writeln!(f)?;
+                methods.insert(method);
+            }
+
+            if !methods.contains(&list_method) {
+                writeln!(f, "    /// Returns all `{}` children", child_type.kind)?;
+                writeln!(
+                    f,
+                    "    pub(crate) fn {}(&self) -> ::std::vec::Vec<{}<'a>> {{",
+                    list_method, child_name
+                )?;
+                writeln!(
+                    f,
+                    "        self.0.children().filter_map({}::cast).collect()",
+                    child_name
+                )?;
+                writeln!(f, "    }}")?;
+                writeln!(f)?;
+                methods.insert(list_method);
+            }
+        }
+
+        writeln!(f, "}}")?;
+        writeln!(f)?;
+    }
+
+    Ok(())
+}
+
+/// Converts a node kind to a type name (e.g. `function_declaration` to `FunctionDeclaration`)
+fn type_name(kind: &str) -> String {
+    kind.split('_')
+        .filter(|s| !s.is_empty())
+        .map(titlecase)
+        .collect::<Vec<String>>()
+        .join("")
+}
+
+/// Panics if two names convert to the same type name, e.g. `a_b` and `a__b`
+fn check_type_names(names: &BTreeSet<&str>) {
+    let mut seen = BTreeMap::new();
+
+    for &name in names {
+        if let Some(other) = seen.insert(type_name(name), name) {
+            panic!(
+                "`{}` and `{}` in {} both convert to the type name `{}`",
+                other,
+                name,
+                node_types_file().display(),
+                type_name(name)
+            );
+        }
+    }
+}
+
+/// Converts a field name or node kind to a method name
+///
+/// Rust keywords get a `_` suffix.  Raw identifiers can't be used because some keywords, e.g.
+/// `crate` and `self`, can't be raw.
+fn method_name(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn",
+        "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+        "return", "self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+        "where", "while", "async", "await", "dyn", "abstract", "become", "box", "do", "final",
+        "macro", "override", "priv", "typeof", "unsized", "virtual", "yield", "try",
+    ];
+
+    if KEYWORDS.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
 fn titlecase(s: &str) -> String {
     let mut result = s.to_string();
     result.get_mut(0..1).unwrap().make_ascii_uppercase();
@@ -0,0 +1,80 @@
+use crate::doc::Doc;
+
+/// Lays out rows of cells in columns
+///
+/// Cells are separated by a space.  If the enclosing group is broken, cells are padded so that each
+/// column starts at the same position in every row.  Padding a cell by more than `max_padding`
+/// spaces, or a cell that spans lines, leaves the rows unaligned.  `Doc::Nil` cells are empty and
+/// columns without any content are left out.
+///
+/// Returns the document for each row.
+pub(crate) fn align_columns<'a>(rows: Vec<Vec<Doc<'a>>>, max_padding: usize) -> Vec<Doc<'a>> {
+    let column_count = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let mut widths = vec![Some(0); column_count];
+
+    for row in &rows {
+        for (column, cell) in row.iter().enumerate() {
+            widths[column] = match (widths[column], cell.flat_width()) {
+                (Some(width), Some(cell_width)) => Some(width.max(cell_width)),
+                _ => None,
+            };
+        }
+    }
+
+    // The last column is never padded
+    let padded_widths = &widths[..column_count.saturating_sub(1)];
+    let aligned = padded_widths.iter().all(Option::is_some)
+        && rows.iter().all(|row| {
+            row.iter().zip(padded_widths).all(|(cell, width)| {
+                width.unwrap_or(0) - cell.flat_width().unwrap_or(0) <= max_padding
+            })
+        });
+    let used = (0..column_count)
+        .map(|column| {
+            rows.iter()
+                .any(|row| column < row.len() && !is_empty(&row[column]))
+        })
+        .collect::<Vec<_>>();
+
+    rows.into_iter()
+        .map(|row| {
+            let mut docs = Vec::new();
+            let mut padding = 0;
+
+            for (column, cell) in row.into_iter().enumerate() {
+                if !used[column] {
+                    continue;
+                }
+
+                let width = widths[column].unwrap_or(0);
+                if is_empty(&cell) {
+                    if aligned {
+                        padding += width + 1;
+                    }
+                    continue;
+                }
+
+                let cell_width = cell.flat_width().unwrap_or(0);
+                if docs.is_empty() {
+                    if padding > 0 {
+                        docs.push(Doc::if_break(Doc::text(" ".repeat(padding)), Doc::Nil));
+                    }
+                } else {
+                    docs.push(Doc::if_break(
+                        Doc::text(" ".repeat(padding + 1)),
+                        Doc::text(" "),
+                    ));
+                }
+                docs.push(cell);
+
+                padding = if aligned { width - cell_width } else { 0 };
+            }
+
+            Doc::concat(docs)
+        })
+        .collect()
+}
+
+fn is_empty(doc: &Doc<'_>) -> bool {
+    matches!(doc, Doc::Nil)
+}
@@ -0,0 +1,303 @@
+use std::io;
+
+use crate::source_map::Mapping;
+
+/// The amount of content held before finished lines are written out
+const FLUSH_THRESHOLD: usize = 64 * 1024;
+
+/// Output buffer that streams finished lines to a writer
+///
+/// Handles indentation, blank line insertion between blocks, and comments at the end of lines.
+/// Only a small window of output is held in memory.  Lines are written to the writer once the
+/// window grows past `FLUSH_THRESHOLD`.
+pub(crate) struct Buffer<W>
+where
+    W: io::Write,
+{
+    writer: W,
+
+    /// Holds content that has not been written yet.
+    ///
+    /// Clients use `push_str()` and `push()` to add content to the buffer.  The last line is always
+    /// held back, along with its newline, so that it can be reopened.  See
+    /// push_end_of_line_comment().
+    content: String,
+
+    /// The first error returned by the writer.
+    ///
+    /// Writing stops on the first error.  The error is returned by finish().
+    error: Option<io::Error>,
+
+    /// The current length of the current line.
+    ///
+    /// As content is pushed into the buffer, the line_length is incremented for every character
+    /// added including indentation.  If a newline character is seen, the line length is reset to 0.
+    line_length: usize,
+
+    /// Indicates whether the next character pushed will start a new line.
+    line_start: bool,
+
+    /// Indicates whether any line has been ended.
+    has_lines: bool,
+
+    /// The current indent level in number of spaces.
+    indent: usize,
+
+    /// The indent of the line in front of which a blank line needs to be inserted.
+    ///
+    /// This gets reset anytime a line is started and gets set by maybe_blank_line().  Clients
+    /// should call maybe_blank_line() at the end of a block.  This allows a blank line to be
+    /// inserted between blocks in a given scope but prevents lines from being inserted before the
+    /// first block and after the last block.
+    insert_blank_line: Option<usize>,
+
+    /// Comments waiting to be added to the end of the current line.
+    ///
+    /// See push_end_of_line_comment().
+    end_of_line_comments: String,
+
+    /// Mappings for the source text in end_of_line_comments.
+    ///
+    /// Output offsets are relative to the start of end_of_line_comments until the comments are
+    /// added to the line.
+    end_of_line_mappings: Vec<Mapping>,
+
+    /// The number of bytes written to the writer.
+    written: usize,
+
+    /// The positions of source text in the output, or None if they are not being recorded.
+    ///
+    /// See push_source().
+    mappings: Option<Vec<Mapping>>,
+
+    /// The source offset of the text being pushed by push_source().
+    ///
+    /// The mapping is recorded once indentation has been added.
+    source_offset: Option<usize>,
+}
+
+impl<W> Buffer<W>
+where
+    W: io::Write,
+{
+    pub(crate) fn new(writer: W) -> Self {
+        Self {
+            writer,
+            content: String::with_capacity(FLUSH_THRESHOLD + 1024),
+            error: None,
+            line_length: 0,
+            line_start: true,
+            has_lines: false,
+            indent: 0,
+            insert_blank_line: None,
+            end_of_line_comments: String::new(),
+            end_of_line_mappings: Vec::new(),
+            written: 0,
+            mappings: None,
+            source_offset: None,
+        }
+    }
+
+    /// Creates a buffer that records where source text ends up in the output
+    pub(crate) fn with_source_map(writer: W) -> Self {
+        Self {
+            mappings: Some(Vec::new()),
+            ..Self::new(writer)
+        }
+    }
+
+    /// Adds a string to the buffer
+    ///
+    /// Adds indentation for new non-blank lines.
+    pub(crate) fn push_str(&mut self, s: &str) {
+        let mut lines = s.split('\n');
+
+        if let Some(line) = lines.next() {
+            self.push_line_content(line);
+        }
+        for line in lines {
+            self.push_newline();
+            self.push_line_content(line);
+        }
+    }
+
+    /// Adds text copied from `source_offset` in the source
+    ///
+    /// Same as push_str() but also records a mapping between the source and the output for each
+    /// line of the text.
+    pub(crate) fn push_source(&mut self, s: &str, source_offset: usize) {
+        let mut offset = source_offset;
+
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.push_newline();
+            }
+            if self.mappings.is_some() {
+                self.source_offset = Some(offset);
+            }
+            self.push_line_content(line);
+            self.source_offset = None;
+
+            offset += line.len() + 1;
+        }
+    }
+
+    /// Adds a character to the buffer
+    pub(crate) fn push(&mut self, c: char) {
+        if c == '\n' {
+            self.push_newline();
+        } else {
+            self.push_line_content(c.encode_utf8(&mut [0; 4]));
+        }
+    }
+
+    /// Adds content that does not contain a newline
+    ///
+    /// Updates line_length.  Adds indentation if the content starts a line.
+    fn push_line_content(&mut self, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+
+        if self.line_start {
+            if self.insert_blank_line.take() == Some(self.indent) {
+                self.content.push('\n');
+            }
+            self.push_indent();
+            self.line_start = false;
+        }
+
+        if let Some(source) = self.source_offset.take() {
+            if let Some(mappings) = &mut self.mappings {
+                mappings.push(Mapping {
+                    source,
+                    output: self.written + self.content.len(),
+                    length: s.len(),
+                });
+            }
+        }
+
+        self.content.push_str(s);
+        self.line_length += s.chars().count();
+    }
+
+    fn push_newline(&mut self) {
+        if !self.end_of_line_comments.is_empty() {
+            let output = self.written + self.content.len();
+            if let Some(mappings) = &mut self.mappings {
+                mappings.extend(self.end_of_line_mappings.drain(..).map(|mapping| Mapping {
+                    output: output + mapping.output,
+                    ..mapping
+                }));
+            }
+
+            self.content.push_str(&self.end_of_line_comments);
+            self.end_of_line_comments.clear();
+        }
+
+        self.content.push('\n');
+        self.line_length = 0;
+        self.line_start = true;
+        self.has_lines = true;
+
+        if self.content.len() >= FLUSH_THRESHOLD {
+            self.flush_finished_lines();
+        }
+    }
+
+    /// Adds the current indentation level to the buffer
+    fn push_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.content.push(' ');
+        }
+        self.line_length += self.indent;
+    }
+
+    /// Writes all content except the last line
+    fn flush_finished_lines(&mut self) {
+        let last_line = &self.content[..self.content.len() - 1];
+
+        if let Some(index) = last_line.rfind('\n') {
+            self.write(index + 1);
+        }
+    }
+
+    /// Writes the first `length` bytes of content
+    fn write(&mut self, length: usize) {
+        if self.error.is_none() {
+            if let Err(e) = self.writer.write_all(&self.content.as_bytes()[..length]) {
+                self.error = Some(e);
+            }
+        }
+
+        self.content.drain(..length);
+        self.written += length;
+    }
+
+    /// Sets the indentation used for lines started after this call
+    pub(crate) fn set_indent(&mut self, indent: usize) {
+        self.indent = indent;
+    }
+
+    /// Adds a comment to the end of the current line
+    ///
+    /// The comment is held until the line ends so that content pushed in the meantime (e.g. a
+    /// closing `;`) stays in front of it.  If the current line is empty, the comment goes at the end
+    /// of the previous line instead.  A mapping is recorded if the comment is copied from
+    /// `source_offset` in the source.
+    pub(crate) fn push_end_of_line_comment(&mut self, comment: &str, source_offset: Option<usize>) {
+        if let (Some(source), Some(_)) = (source_offset, &self.mappings) {
+            self.end_of_line_mappings.push(Mapping {
+                source,
+                output: self.end_of_line_comments.len(),
+                length: comment.len(),
+            });
+        }
+        self.end_of_line_comments.push_str(comment);
+
+        if self.line_start && self.has_lines {
+            self.content.pop();
+            self.push_newline();
+        }
+    }
+
+    /// Ends the current line if any end-of-line comments are still waiting for it and writes all
+    /// remaining content
+    ///
+    /// Returns the mappings recorded by push_source(), if any.
+    pub(crate) fn finish(mut self) -> io::Result<Vec<Mapping>> {
+        if !self.end_of_line_comments.is_empty() {
+            self.push_newline();
+        }
+
+        let length = self.content.len();
+        self.write(length);
+
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => {
+                self.writer.flush()?;
+                Ok(self.mappings.take().unwrap_or_default())
+            }
+        }
+    }
+
+    /// Indicates whether the next character pushed will start a new line
+    pub(crate) fn is_line_start(&self) -> bool {
+        self.line_start
+    }
+
+    pub(crate) fn line_length(&self) -> usize {
+        self.line_length
+    }
+
+    /// Inserts a blank line if the next line starts at `indent`
+    ///
+    /// Nothing is inserted if the content so far is indented less than `indent`, i.e. if no block
+    /// has ended in the scope yet.
+    pub(crate) fn maybe_blank_line(&mut self, indent: usize) {
+        if self.indent >= indent {
+            self.insert_blank_line = Some(indent);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use tree_sitter::Node;
+
+use crate::Symbol;
+
+/// Identifies a node independent of the tree cursor used to reach it
+///
+/// Nodes that share a range (e.g. a node with a single child) are told apart by their kind.
+type NodeKey = (usize, usize, u16);
+
+fn key(node: Node<'_>) -> NodeKey {
+    (node.start_byte(), node.end_byte(), node.kind_id())
+}
+
+pub(crate) fn is_comment(node: Node<'_>) -> bool {
+    node.kind() == Symbol::Comment.name()
+}
+
+/// Indicates whether `node` directly follows a comment
+pub(crate) fn follows_comment(node: Node<'_>) -> bool {
+    matches!(node.prev_sibling(), Some(prev) if is_comment(prev))
+}
+
+/// Comments attached to the syntax nodes they belong to
+///
+/// Tree-sitter parses comments as extras.  They can appear as a child of any node which makes
+/// them awkward for formatting rules to handle.  Instead, each comment is attached to a
+/// neighbouring node as one of:
+///
+/// * Leading: the comment precedes its node (e.g. a comment on the line above a statement)
+/// * Trailing: the comment follows its node (e.g. a comment at the end of a statement's line)
+/// * Dangling: the comment has no named siblings (e.g. a comment in an empty argument list)
+///
+/// Formatting rules then emit comments relative to the node they are attached to.
+pub(crate) struct Comments<'a> {
+    leading: HashMap<NodeKey, Vec<Node<'a>>>,
+    trailing: HashMap<NodeKey, Vec<Node<'a>>>,
+    dangling: HashMap<NodeKey, Vec<Node<'a>>>,
+
+    /// All comments in source order
+    all: Vec<Node<'a>>,
+
+    /// Comments that have already been written to a buffer
+    ///
+    /// Prevents a comment from being emitted twice and allows any comment that a rule didn't
+    /// emit to be recovered instead of dropped.
+    emitted: RefCell<HashSet<NodeKey>>,
+}
+
+impl<'a> Comments<'a> {
+    pub(crate) fn attach(root: Node<'a>) -> Self {
+        let mut comments = Self {
+            leading: HashMap::new(),
+            trailing: HashMap::new(),
+            dangling: HashMap::new(),
+            all: Vec::new(),
+            emitted: RefCell::new(HashSet::new()),
+        };
+
+        comments.attach_children(root);
+        comments.all.sort_by_key(|node| node.start_byte());
+
+        comments
+    }
+
+    fn attach_children(&mut self, node: Node<'a>) {
+        let children = node.children().collect::<Vec<_>>();
+        let is_neighbour = |node: &&Node<'a>| node.is_named() && !is_comment(**node);
+
+        for (i, &child) in children.iter().enumerate() {
+            if !is_comment(child) {
+                self.attach_children(child);
+                continue;
+            }
+
+            let preceding = children[..i].iter().rev().find(is_neighbour);
+            let following = children[i + 1..].iter().find(is_neighbour);
+
+            let (map, target) = match (preceding, following) {
+                (Some(&preceding), _)
+                    if preceding.end_position().row == child.start_position().row =>
+                {
+                    (&mut self.trailing, preceding)
+                }
+                (_, Some(&following)) => (&mut self.leading, following),
+                (Some(&preceding), None) => (&mut self.trailing, preceding),
+                (None, None) => (&mut self.dangling, node),
+            };
+
+            map.entry(key(target)).or_insert_with(Vec::new).push(child);
+            self.all.push(child);
+        }
+    }
+
+    pub(crate) fn leading(&self, node: Node<'a>) -> &[Node<'a>] {
+        Self::lookup(&self.leading, node)
+    }
+
+    pub(crate) fn trailing(&self, node: Node<'a>) -> &[Node<'a>] {
+        Self::lookup(&self.trailing, node)
+    }
+
+    pub(crate) fn dangling(&self, node: Node<'a>) -> &[Node<'a>] {
+        Self::lookup(&self.dangling, node)
+    }
+
+    fn lookup<'m>(map: &'m HashMap<NodeKey, Vec<Node<'a>>>, node: Node<'a>) -> &'m [Node<'a>] {
+        map.get(&key(node)).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the comments within `node` that have not been emitted yet
+    pub(crate) fn remaining(&self, node: Node<'a>) -> Vec<Node<'a>> {
+        let emitted = self.emitted.borrow();
+        let start = match self
+            .all
+            .binary_search_by_key(&node.start_byte(), |comment| comment.start_byte())
+        {
+            Ok(index) | Err(index) => index,
+        };
+
+        self.all[start..]
+            .iter()
+            .take_while(|comment| comment.end_byte() <= node.end_byte())
+            .filter(|&&comment| !emitted.contains(&key(comment)))
+            .cloned()
+            .collect()
+    }
+
+    /// Marks a comment as emitted
+    ///
+    /// Returns false if the comment has already been emitted.
+    pub(crate) fn take(&self, comment: Node<'a>) -> bool {
+        self.emitted.borrow_mut().insert(key(comment))
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use tree_sitter::{Node, Point};
+
+/// The source location of a syntax node that a diagnostic refers to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    /// The file the node was parsed from, if known.
+    ///
+    /// The formatter only sees source text so this is filled in after the fact by
+    /// `Error::with_path()`.
+    pub path: Option<PathBuf>,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub start: Point,
+    pub end: Point,
+    pub kind: &'static str,
+}
+
+impl Location {
+    pub fn new(node: Node<'_>) -> Self {
+        Self {
+            path: None,
+            start_byte: node.start_byte(),
+            end_byte: node.end_byte(),
+            start: node.start_position(),
+            end: node.end_position(),
+            kind: node.kind(),
+        }
+    }
+
+    /// Renders a compiler-style diagnostic
+    ///
+    /// The diagnostic shows the message, the location, the first source line of the node, and a
+    /// caret underline of the node on that line.  The underline stops at the end of the line for
+    /// nodes that span multiple lines.
+    pub fn render(&self, message: &str, source: &str) -> String {
+        let line = source.lines().nth(self.start.row).unwrap_or("");
+        let line_number = (self.start.row + 1).to_string();
+        let gutter = " ".repeat(line_number.len());
+
+        let start_column = self.start.column.min(line.len());
+        let end_column = if self.end.row == self.start.row {
+            self.end.column.min(line.len())
+        } else {
+            line.len()
+        };
+
+        // Mirror tabs so the underline lines up regardless of tab width
+        let padding = line
+            .get(..start_column)
+            .unwrap_or("")
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let underline_length = line
+            .get(start_column..end_column)
+            .map_or(0, |s| s.chars().count())
+            .max(1);
+
+        let mut s = String::new();
+        s.push_str(&format!("error: {}\n", message));
+        s.push_str(&format!("{}--> {}\n", gutter, self));
+        s.push_str(&format!("{} |\n", gutter));
+        s.push_str(&format!("{} | {}\n", line_number, line));
+        s.push_str(&format!(
+            "{} | {}{} {}\n",
+            gutter,
+            padding,
+            "^".repeat(underline_length),
+            self.kind
+        ));
+        s
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+        }
+        write!(f, "{}:{}", self.start.row + 1, self.start.column + 1)
+    }
+}
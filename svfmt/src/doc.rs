@@ -0,0 +1,321 @@
+use std::borrow::Cow;
+use std::io;
+
+use crate::buffer::Buffer;
+
+/// The number of spaces added by `Doc::Indent`
+pub(crate) const INDENT: usize = 4;
+
+/// A document describing formatted output along with where it may be broken across lines
+///
+/// Formatting rules build documents instead of writing text directly.  The renderer then decides
+/// which groups fit on the current line and which need to be broken.  This is the model described
+/// by Wadler in "A prettier printer" and used by Prettier.
+#[derive(Debug, Clone)]
+pub enum Doc<'a> {
+    Nil,
+
+    /// Text without line breaks
+    ///
+    /// Block comments are the exception.  Their inner lines are indented by the buffer.
+    Text(Cow<'a, str>),
+
+    /// Text copied from a byte offset in the source, e.g. a token
+    ///
+    /// Rendered like `Text`.  The offset lets the buffer record a source map.
+    Source(Cow<'a, str>, usize),
+
+    /// A space if the enclosing group fits on the line, a line break otherwise
+    Line,
+
+    /// Nothing if the enclosing group fits on the line, a line break otherwise
+    SoftLine,
+
+    /// A line break regardless of the enclosing group
+    HardLine,
+
+    /// A line break unless already at the start of a line
+    LineStart,
+
+    /// A blank line if more content follows at the same indentation.  See `Buffer::maybe_blank_line()`.
+    MaybeBlankLine,
+
+    /// Text added to the end of the line the renderer is on, e.g. a trailing comment
+    ///
+    /// The offset is that of the text in the source, if it is copied from the source.
+    LineSuffix(Cow<'a, str>, Option<usize>),
+
+    Concat(Vec<Doc<'a>>),
+
+    /// Content that is rendered on a single line if it fits, otherwise its lines are broken
+    Group(Box<Doc<'a>>),
+
+    /// Content whose lines are indented one level more than the enclosing content
+    Indent(Box<Doc<'a>>),
+
+    /// Content whose lines are indented to the column the content starts at
+    Align(Box<Doc<'a>>),
+
+    /// The first document if the enclosing group is broken, the second if it fits on the line
+    IfBreak(Box<Doc<'a>>, Box<Doc<'a>>),
+}
+
+impl<'a> Doc<'a> {
+    pub fn text<T>(text: T) -> Self
+    where
+        T: Into<Cow<'a, str>>,
+    {
+        Doc::Text(text.into())
+    }
+
+    pub fn source<T>(text: T, offset: usize) -> Self
+    where
+        T: Into<Cow<'a, str>>,
+    {
+        Doc::Source(text.into(), offset)
+    }
+
+    pub fn line_suffix<T>(text: T) -> Self
+    where
+        T: Into<Cow<'a, str>>,
+    {
+        Doc::LineSuffix(text.into(), None)
+    }
+
+    /// A line suffix copied from a byte offset in the source.  See `Doc::source()`.
+    pub fn line_suffix_source<T>(text: T, offset: usize) -> Self
+    where
+        T: Into<Cow<'a, str>>,
+    {
+        Doc::LineSuffix(text.into(), Some(offset))
+    }
+
+    pub fn concat(docs: Vec<Doc<'a>>) -> Self {
+        Doc::Concat(docs)
+    }
+
+    pub fn group(doc: Doc<'a>) -> Self {
+        Doc::Group(Box::new(doc))
+    }
+
+    pub fn indent(doc: Doc<'a>) -> Self {
+        Doc::Indent(Box::new(doc))
+    }
+
+    pub fn align(doc: Doc<'a>) -> Self {
+        Doc::Align(Box::new(doc))
+    }
+
+    pub fn if_break(broken: Doc<'a>, flat: Doc<'a>) -> Self {
+        Doc::IfBreak(Box::new(broken), Box::new(flat))
+    }
+
+    /// Concatenates documents with a separator between each
+    pub fn join<I>(docs: I, separator: Doc<'a>) -> Self
+    where
+        I: IntoIterator<Item = Doc<'a>>,
+    {
+        let mut joined = Vec::new();
+
+        for doc in docs {
+            if !joined.is_empty() {
+                joined.push(separator.clone());
+            }
+            joined.push(doc);
+        }
+
+        Doc::Concat(joined)
+    }
+
+    /// Returns the width of the document rendered on a single line
+    ///
+    /// Returns `None` if the document contains a line break that can't be flattened.
+    pub(crate) fn flat_width(&self) -> Option<usize> {
+        match self {
+            Doc::Nil | Doc::SoftLine | Doc::MaybeBlankLine | Doc::LineSuffix(..) => Some(0),
+            Doc::Text(text) | Doc::Source(text, _) => {
+                if text.contains('\n') {
+                    None
+                } else {
+                    Some(text.chars().count())
+                }
+            }
+            Doc::Line => Some(1),
+            Doc::HardLine | Doc::LineStart => None,
+            Doc::Concat(docs) => docs.iter().map(Doc::flat_width).sum(),
+            Doc::Group(doc) | Doc::Indent(doc) | Doc::Align(doc) | Doc::IfBreak(_, doc) => {
+                doc.flat_width()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+#[derive(Clone, Copy)]
+struct Command<'d, 'a> {
+    indent: usize,
+    mode: Mode,
+    doc: &'d Doc<'a>,
+}
+
+/// Renders a document into a buffer, breaking groups that don't fit in `width` columns
+pub(crate) fn render<W>(doc: &Doc<'_>, buffer: &mut Buffer<W>, width: usize)
+where
+    W: io::Write,
+{
+    let mut stack = vec![Command {
+        indent: 0,
+        mode: Mode::Break,
+        doc,
+    }];
+
+    while let Some(command) = stack.pop() {
+        let Command { indent, mode, doc } = command;
+
+        match doc {
+            Doc::Nil => {}
+            Doc::Text(text) => {
+                buffer.set_indent(indent);
+                buffer.push_str(text);
+            }
+            Doc::Source(text, offset) => {
+                buffer.set_indent(indent);
+                buffer.push_source(text, *offset);
+            }
+            Doc::Line => match mode {
+                Mode::Flat => buffer.push(' '),
+                Mode::Break => buffer.push('\n'),
+            },
+            Doc::SoftLine => {
+                if mode == Mode::Break {
+                    buffer.push('\n');
+                }
+            }
+            Doc::HardLine => buffer.push('\n'),
+            Doc::LineStart => {
+                if !buffer.is_line_start() {
+                    buffer.push('\n');
+                }
+            }
+            Doc::MaybeBlankLine => buffer.maybe_blank_line(indent),
+            Doc::LineSuffix(text, offset) => buffer.push_end_of_line_comment(text, *offset),
+            Doc::Concat(docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push(Command { indent, mode, doc });
+                }
+            }
+            Doc::Group(doc) => {
+                let flat = Command {
+                    indent,
+                    mode: Mode::Flat,
+                    doc,
+                };
+                let column = column(buffer, indent);
+                let mode =
+                    if mode == Mode::Flat || fits(flat, &stack, width as isize - column as isize) {
+                        Mode::Flat
+                    } else {
+                        Mode::Break
+                    };
+
+                stack.push(Command { indent, mode, doc });
+            }
+            Doc::Indent(doc) => stack.push(Command {
+                indent: indent + INDENT,
+                mode,
+                doc,
+            }),
+            Doc::Align(doc) => stack.push(Command {
+                indent: column(buffer, indent),
+                mode,
+                doc,
+            }),
+            Doc::IfBreak(broken, flat) => stack.push(Command {
+                indent,
+                mode,
+                doc: if mode == Mode::Break { broken } else { flat },
+            }),
+        }
+    }
+}
+
+/// Returns the column the next character pushed to the buffer will be placed at
+fn column<W>(buffer: &Buffer<W>, indent: usize) -> usize
+where
+    W: io::Write,
+{
+    if buffer.is_line_start() {
+        indent
+    } else {
+        buffer.line_length()
+    }
+}
+
+/// Determines whether `next` fits in the `remaining` width of the current line
+///
+/// Measures `next` in flat mode followed by the content after it up to the next line break.
+/// Trailing comments inside `next` never fit so that they stay next to the code they follow.
+fn fits(next: Command<'_, '_>, rest: &[Command<'_, '_>], mut remaining: isize) -> bool {
+    let mut rest_index = rest.len();
+    let mut stack = vec![(next.mode, next.doc, false)];
+
+    loop {
+        if remaining < 0 {
+            return false;
+        }
+
+        let (mode, doc, in_rest) = match stack.pop() {
+            Some(item) => item,
+            None => {
+                if rest_index == 0 {
+                    return true;
+                }
+                rest_index -= 1;
+                (rest[rest_index].mode, rest[rest_index].doc, true)
+            }
+        };
+
+        match doc {
+            Doc::Nil | Doc::MaybeBlankLine => {}
+            Doc::Text(text) | Doc::Source(text, _) => match text.find('\n') {
+                Some(index) => return remaining >= text[..index].chars().count() as isize,
+                None => remaining -= text.chars().count() as isize,
+            },
+            Doc::Line => match mode {
+                Mode::Flat => remaining -= 1,
+                Mode::Break => return true,
+            },
+            Doc::SoftLine => {
+                if mode == Mode::Break {
+                    return true;
+                }
+            }
+            Doc::HardLine | Doc::LineStart => return true,
+            Doc::LineSuffix(..) => {
+                if !in_rest {
+                    return false;
+                }
+            }
+            Doc::Concat(docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push((mode, doc, in_rest));
+                }
+            }
+            Doc::Group(doc) | Doc::Indent(doc) | Doc::Align(doc) => {
+                stack.push((mode, doc, in_rest));
+            }
+            Doc::IfBreak(broken, flat) => {
+                stack.push((
+                    mode,
+                    if mode == Mode::Break { broken } else { flat },
+                    in_rest,
+                ));
+            }
+        }
+    }
+}
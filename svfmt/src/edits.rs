@@ -0,0 +1,299 @@
+use std::ops::{Index, IndexMut, Range};
+
+/// A change to apply to the original text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    /// The byte range of the original text to replace
+    pub range: Range<usize>,
+
+    /// The text to replace the range with
+    pub replacement: String,
+}
+
+/// Returns the edits that turn `original` into `formatted`
+///
+/// Editors can apply the edits instead of replacing the whole document, which keeps undo history,
+/// folds and marks outside the changed regions intact.  Lines are compared first.  Each run of
+/// changed lines is then compared token by token so that e.g. a change in spacing replaces only
+/// the spacing.
+///
+/// The edits are ordered and don't overlap.  Ranges refer to the original text so the edits must
+/// be applied in reverse order, or all at once as LSP clients do.
+pub fn text_edits(original: &str, formatted: &str) -> Vec<TextEdit> {
+    let original_lines = original.split_inclusive('\n').collect::<Vec<_>>();
+    let formatted_lines = formatted.split_inclusive('\n').collect::<Vec<_>>();
+    let original_starts = starts(&original_lines);
+    let formatted_starts = starts(&formatted_lines);
+
+    let mut edits = Vec::new();
+
+    for (lines, replacement_lines) in diff(&original_lines, &formatted_lines) {
+        let base = original_starts[lines.start];
+        let text = &original[base..original_starts[lines.end]];
+        let replacement_text = &formatted
+            [formatted_starts[replacement_lines.start]..formatted_starts[replacement_lines.end]];
+
+        let original_tokens = tokens(text);
+        let replacement_tokens = tokens(replacement_text);
+        let token_starts = starts(&original_tokens);
+
+        for (range, replacement) in diff(&original_tokens, &replacement_tokens) {
+            edits.push(TextEdit {
+                range: base + token_starts[range.start]..base + token_starts[range.end],
+                replacement: replacement_tokens[replacement].concat(),
+            });
+        }
+    }
+
+    edits
+}
+
+/// Returns the byte offset of each piece of text, followed by the offset of the end
+fn starts(pieces: &[&str]) -> Vec<usize> {
+    let mut starts = Vec::with_capacity(pieces.len() + 1);
+    let mut offset = 0;
+
+    starts.push(offset);
+    for piece in pieces {
+        offset += piece.len();
+        starts.push(offset);
+    }
+
+    starts
+}
+
+/// Splits text into runs of whitespace, runs of word characters and single other characters
+fn tokens(text: &str) -> Vec<&str> {
+    #[derive(PartialEq)]
+    enum Class {
+        Space,
+        Word,
+        Other,
+    }
+
+    let class = |c: char| {
+        if c.is_whitespace() {
+            Class::Space
+        } else if c.is_alphanumeric() || c == '_' {
+            Class::Word
+        } else {
+            Class::Other
+        }
+    };
+
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut previous = None;
+
+    for (offset, c) in text.char_indices() {
+        let current = class(c);
+        if offset > start && (current == Class::Other || Some(&current) != previous.as_ref()) {
+            tokens.push(&text[start..offset]);
+            start = offset;
+        }
+        previous = Some(current);
+    }
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+
+    tokens
+}
+
+/// Returns the ranges of `a` and `b` that differ, in order
+///
+/// Uses the algorithm from Myers' "An O(ND) Difference Algorithm and Its Variations", which finds
+/// the shortest edit script.  Common leading and trailing items are skipped first since formatting
+/// usually changes little.
+fn diff<T>(a: &[T], b: &[T]) -> Vec<(Range<usize>, Range<usize>)>
+where
+    T: PartialEq,
+{
+    let prefix = common_prefix(a, b);
+    let suffix = common_suffix(&a[prefix..], &b[prefix..]);
+
+    let a_middle = &a[prefix..a.len() - suffix];
+    let b_middle = &b[prefix..b.len() - suffix];
+
+    let mut hunks = Vec::new();
+    let (mut i, mut j) = (0, 0);
+
+    for (x, y) in matches(a_middle, b_middle)
+        .into_iter()
+        .chain(Some((a_middle.len(), b_middle.len())))
+    {
+        if x > i || y > j {
+            hunks.push((prefix + i..prefix + x, prefix + j..prefix + y));
+        }
+        i = x + 1;
+        j = y + 1;
+    }
+
+    hunks
+}
+
+/// Returns the pairs of indices of matching items in a longest common subsequence of `a` and `b`
+///
+/// Uses the linear space refinement from section 4b of the paper: the middle snake of an optimal
+/// path is found by searching from both ends at once, and the parts before and after it are solved
+/// recursively.
+fn matches<T>(a: &[T], b: &[T]) -> Vec<(usize, usize)>
+where
+    T: PartialEq,
+{
+    let max = (a.len() + b.len()) / 2 + 2;
+    let mut forward = Diagonals::new(max);
+    let mut backward = Diagonals::new(max);
+    let mut matches = Vec::new();
+
+    collect_matches(a, b, (0, 0), &mut forward, &mut backward, &mut matches);
+
+    matches
+}
+
+/// Appends the matches of `a` and `b` to `matches`, with `start` added to their indices
+fn collect_matches<T>(
+    mut a: &[T],
+    mut b: &[T],
+    mut start: (usize, usize),
+    forward: &mut Diagonals,
+    backward: &mut Diagonals,
+    matches: &mut Vec<(usize, usize)>,
+) where
+    T: PartialEq,
+{
+    let prefix = common_prefix(a, b);
+    matches.extend((0..prefix).map(|i| (start.0 + i, start.1 + i)));
+    a = &a[prefix..];
+    b = &b[prefix..];
+    start = (start.0 + prefix, start.1 + prefix);
+
+    let suffix = common_suffix(a, b);
+    let suffix_start = (start.0 + a.len() - suffix, start.1 + b.len() - suffix);
+    a = &a[..a.len() - suffix];
+    b = &b[..b.len() - suffix];
+
+    if !a.is_empty() && !b.is_empty() {
+        let (x, y) = middle_snake(a, b, forward, backward);
+
+        collect_matches(&a[..x], &b[..y], start, forward, backward, matches);
+        collect_matches(
+            &a[x..],
+            &b[y..],
+            (start.0 + x, start.1 + y),
+            forward,
+            backward,
+            matches,
+        );
+    }
+
+    matches.extend((0..suffix).map(|i| (suffix_start.0 + i, suffix_start.1 + i)));
+}
+
+/// Returns the start of the middle snake of a shortest edit script for `a` and `b`
+///
+/// Both slices must be non-empty.  The returned point splits the script into two halves that
+/// don't depend on each other.
+fn middle_snake<T>(
+    a: &[T],
+    b: &[T],
+    forward: &mut Diagonals,
+    backward: &mut Diagonals,
+) -> (usize, usize)
+where
+    T: PartialEq,
+{
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+
+    // forward[k] is the furthest x reached on diagonal k = x - y from the start.  backward[k] is
+    // the same measured from the end, i.e. for the reversed slices.
+    forward[1] = 0;
+    backward[1] = 0;
+
+    for d in 0..=(n + m + 1) / 2 {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && forward[k - 1] < forward[k + 1]) {
+                forward[k + 1]
+            } else {
+                forward[k - 1] + 1
+            };
+            let y = x - k;
+            let snake_start = (x as usize, y as usize);
+
+            if x < n && y < m {
+                x += common_prefix(&a[x as usize..], &b[y as usize..]) as isize;
+            }
+            forward[k] = x;
+
+            if odd && (k - delta).abs() < d && forward[k] + backward[delta - k] >= n {
+                return snake_start;
+            }
+        }
+
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && backward[k - 1] < backward[k + 1]) {
+                backward[k + 1]
+            } else {
+                backward[k - 1] + 1
+            };
+            let mut y = x - k;
+
+            if x < n && y < m {
+                let snake = common_suffix(&a[..(n - x) as usize], &b[..(m - y) as usize]);
+                x += snake as isize;
+                y += snake as isize;
+            }
+            backward[k] = x;
+
+            if !odd && (k - delta).abs() <= d && backward[k] + forward[delta - k] >= n {
+                return ((n - x) as usize, (m - y) as usize);
+            }
+        }
+    }
+
+    unreachable!("the forward and backward searches always meet")
+}
+
+fn common_prefix<T: PartialEq>(a: &[T], b: &[T]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+fn common_suffix<T: PartialEq>(a: &[T], b: &[T]) -> usize {
+    a.iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count()
+}
+
+/// The furthest x reached on each diagonal, indexed by diagonal from `-max` to `max`
+struct Diagonals {
+    max: isize,
+    xs: Vec<isize>,
+}
+
+impl Diagonals {
+    fn new(max: usize) -> Self {
+        Diagonals {
+            max: max as isize,
+            xs: vec![0; 2 * max + 1],
+        }
+    }
+}
+
+impl Index<isize> for Diagonals {
+    type Output = isize;
+
+    fn index(&self, k: isize) -> &isize {
+        &self.xs[(self.max + k) as usize]
+    }
+}
+
+impl IndexMut<isize> for Diagonals {
+    fn index_mut(&mut self, k: isize) -> &mut isize {
+        &mut self.xs[(self.max + k) as usize]
+    }
+}
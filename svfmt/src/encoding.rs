@@ -0,0 +1,159 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+use std::str::{self, FromStr};
+
+use snafu::ensure;
+
+use crate::{InvalidEncoding, Result};
+
+/// The UTF-8 encoding of U+FEFF, which some editors write at the start of files
+pub const BYTE_ORDER_MARK: &[u8] = b"\xef\xbb\xbf";
+
+/// Splits a UTF-8 byte-order mark off the start of `source`
+///
+/// Returns whether there was one along with the rest of the source.  The mark is not part of the
+/// code and would otherwise end up in the first token.
+pub fn split_byte_order_mark(source: &[u8]) -> (bool, &[u8]) {
+    if source.starts_with(BYTE_ORDER_MARK) {
+        (true, &source[BYTE_ORDER_MARK.len()..])
+    } else {
+        (false, source)
+    }
+}
+
+/// The character encoding of a source file
+///
+/// Sources are decoded to UTF-8 for parsing and formatting and the output is encoded back.  Both
+/// directions are lossless so comments and strings keep their exact bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+
+    /// ISO-8859-1, common in older vendor IP
+    Latin1,
+}
+
+impl Encoding {
+    /// Returns UTF-8 if `source` is valid UTF-8 and Latin-1 otherwise
+    ///
+    /// Latin-1 maps every byte to a character so it accepts any source.
+    pub fn detect(source: &[u8]) -> Self {
+        match str::from_utf8(source) {
+            Ok(_) => Encoding::Utf8,
+            Err(_) => Encoding::Latin1,
+        }
+    }
+
+    /// Decodes `source` to UTF-8
+    pub fn decode(self, source: &[u8]) -> Result<Cow<'_, str>> {
+        match self {
+            Encoding::Utf8 => match str::from_utf8(source) {
+                Ok(source) => Ok(Cow::Borrowed(source)),
+                Err(e) => InvalidEncoding {
+                    encoding: self,
+                    offset: e.valid_up_to(),
+                }
+                .fail(),
+            },
+            Encoding::Latin1 => Ok(source.iter().map(|&b| char::from(b)).collect()),
+        }
+    }
+
+    /// Encodes UTF-8 `text` back to this encoding
+    pub fn encode(self, text: &str) -> Result<Cow<'_, [u8]>> {
+        match self {
+            Encoding::Utf8 => Ok(Cow::Borrowed(text.as_bytes())),
+            Encoding::Latin1 => {
+                let mut bytes = Vec::with_capacity(text.len());
+
+                for (offset, c) in text.char_indices() {
+                    ensure!(
+                        (c as u32) <= 0xff,
+                        InvalidEncoding {
+                            encoding: self,
+                            offset
+                        }
+                    );
+                    bytes.push(c as u8);
+                }
+
+                Ok(Cow::Owned(bytes))
+            }
+        }
+    }
+
+    /// Wraps a writer so that UTF-8 written to it is encoded in this encoding
+    pub fn writer<W>(self, writer: W) -> Encoder<W>
+    where
+        W: io::Write,
+    {
+        Encoder {
+            writer,
+            encoding: self,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::Utf8 => write!(f, "UTF-8"),
+            Encoding::Latin1 => write!(f, "Latin-1"),
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "latin-1" | "latin1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            _ => Err(format!("Unknown encoding '{}'", s)),
+        }
+    }
+}
+
+/// A writer that encodes the UTF-8 written to it.  See `Encoding::writer()`.
+pub struct Encoder<W>
+where
+    W: io::Write,
+{
+    writer: W,
+    encoding: Encoding,
+
+    /// The start of a character split across writes
+    pending: Vec<u8>,
+}
+
+impl<W> io::Write for Encoder<W>
+where
+    W: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+
+        let length = match str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+
+        let text = str::from_utf8(&self.pending[..length]).unwrap();
+        let bytes = self
+            .encoding
+            .encode(text)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        self.writer.write_all(&bytes)?;
+
+        self.pending.drain(..length);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+
+use crate::{format_with_config, parse, tree_sitter_verilog, Config, Result};
+
+/// The number of unchanged lines shown around the unstable region
+const CONTEXT: usize = 3;
+
+/// Output that changed when it was formatted a second time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instability {
+    /// The output of formatting the source
+    pub first_pass: String,
+
+    /// The output of formatting `first_pass`
+    pub second_pass: String,
+}
+
+/// Formats `source` and then formats the output again
+///
+/// Formatting is expected to be idempotent, i.e. formatting already formatted code leaves it as it
+/// is.  Returns the outputs of both passes if they differ.  `source` is Verilog; see
+/// `Session::verify_idempotent()` for other languages.
+pub fn verify_idempotent(source: &str, config: &Config) -> Result<Option<Instability>> {
+    verify_idempotent_with(source, |source| format_to_string(source, config))
+}
+
+/// Verifies like `verify_idempotent()` with `format` formatting each pass
+pub(crate) fn verify_idempotent_with<F>(source: &str, mut format: F) -> Result<Option<Instability>>
+where
+    F: FnMut(&str) -> Result<String>,
+{
+    let first_pass = format(source)?;
+    let second_pass = format(&first_pass)?;
+
+    if first_pass == second_pass {
+        Ok(None)
+    } else {
+        Ok(Some(Instability {
+            first_pass,
+            second_pass,
+        }))
+    }
+}
+
+fn format_to_string(source: &str, config: &Config) -> Result<String> {
+    let tree = parse(unsafe { tree_sitter_verilog() }, source)?;
+    let mut output = Vec::new();
+    format_with_config(&mut output, source, &tree, config)?;
+
+    // The source is UTF-8 so the output is as well
+    Ok(String::from_utf8(output).unwrap())
+}
+
+/// Renders the unstable region as a unified diff hunk from the first pass to the second
+impl fmt::Display for Instability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let first = self.first_pass.split('\n').collect::<Vec<_>>();
+        let second = self.second_pass.split('\n').collect::<Vec<_>>();
+
+        let prefix = first
+            .iter()
+            .zip(&second)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = first[prefix..]
+            .iter()
+            .rev()
+            .zip(second[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let start = prefix.saturating_sub(CONTEXT);
+        let first_end = (first.len() - suffix + CONTEXT).min(first.len());
+        let second_end = (second.len() - suffix + CONTEXT).min(second.len());
+
+        writeln!(
+            f,
+            "@@ -{},{} +{},{} @@",
+            start + 1,
+            first_end - start,
+            start + 1,
+            second_end - start
+        )?;
+        for line in &first[start..prefix] {
+            writeln!(f, " {}", line)?;
+        }
+        for line in &first[prefix..first.len() - suffix] {
+            writeln!(f, "-{}", line)?;
+        }
+        for line in &second[prefix..second.len() - suffix] {
+            writeln!(f, "+{}", line)?;
+        }
+        for line in &first[first.len() - suffix..first_end] {
+            writeln!(f, " {}", line)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use snafu::ensure;
+use tree_sitter::{Language, Node};
+
+use crate::{tree_sitter_verilog, MissingRuleKinds, Result, Symbol};
+
+/// Node kinds that the built-in formatting rules match on
+const RULE_SYMBOLS: &[Symbol] = &[
+    Symbol::AlwaysConstruct,
+    Symbol::BlockItemDeclaration,
+    Symbol::CaseInsideItem,
+    Symbol::CaseItem,
+    Symbol::CaseStatement,
+    Symbol::ClassDeclaration,
+    Symbol::Comment,
+    Symbol::ConditionalStatement,
+    Symbol::ContinuousAssign,
+    Symbol::EventControl,
+    Symbol::EventExpression,
+    Symbol::Expression,
+    Symbol::FinalConstruct,
+    Symbol::FunctionDeclaration,
+    Symbol::FunctionStatementOrNull,
+    Symbol::HierarchicalInstance,
+    Symbol::InitialConstruct,
+    Symbol::IntegerAtomType,
+    Symbol::InterfaceAnsiHeader,
+    Symbol::InterfaceDeclaration,
+    Symbol::InterfaceNonansiHeader,
+    Symbol::JumpStatement,
+    Symbol::ListOfArgumentsParent,
+    Symbol::ListOfPortDeclarations,
+    Symbol::ListOfPorts,
+    Symbol::LoopStatement,
+    Symbol::ModuleAnsiHeader,
+    Symbol::ModuleDeclaration,
+    Symbol::ModuleInstantiation,
+    Symbol::ModuleNonansiHeader,
+    Symbol::NamedParameterAssignment,
+    Symbol::NamedPortConnection,
+    Symbol::OperatorAssignment,
+    Symbol::PackedDimension,
+    Symbol::ParameterPortList,
+    Symbol::ParameterValueAssignment,
+    Symbol::PrimaryLiteral,
+    Symbol::ProceduralTimingControlStatement,
+    Symbol::SeqBlock,
+    Symbol::SimpleIdentifier,
+    Symbol::StatementOrNull,
+    Symbol::UnpackedDimension,
+];
+
+/// Returns the node kinds that built-in rules match on but `language` doesn't define
+///
+/// Rules for missing kinds never apply, so their constructs are formatted like constructs without
+/// a rule.  An empty result means the rules and the grammar agree.
+pub fn missing_rule_kinds(language: Language) -> Vec<&'static str> {
+    missing_kinds(&named_kind_ids(language))
+}
+
+fn missing_kinds(ids: &HashMap<&'static str, Vec<u16>>) -> Vec<&'static str> {
+    RULE_SYMBOLS
+        .iter()
+        .map(|symbol| symbol.name())
+        .filter(|kind| !ids.contains_key(kind))
+        .collect()
+}
+
+/// Returns the ids of the named node kinds of `language` by name
+///
+/// A kind has several ids if the grammar aliases other nodes to it.
+fn named_kind_ids(language: Language) -> HashMap<&'static str, Vec<u16>> {
+    let mut ids = HashMap::new();
+
+    for id in 0..language.node_kind_count() as u16 {
+        if language.node_kind_is_named(id) {
+            ids.entry(language.node_kind_for_id(id))
+                .or_insert_with(Vec::new)
+                .push(id);
+        }
+    }
+
+    ids
+}
+
+/// Maps the numeric ids used by a parser to symbols
+///
+/// Ids differ between grammars and between versions of a grammar, so a table is only valid for
+/// trees produced by a parser for the language it was built from.  Building a table walks every
+/// node kind of the language, so tables are built once per session or thread and reused.
+pub(crate) struct Kinds {
+    /// Symbols indexed by node kind id
+    symbols: Vec<Symbol>,
+
+    /// Kinds that built-in rules match on but the language doesn't define
+    missing: Vec<&'static str>,
+}
+
+impl Kinds {
+    /// Builds the table for `language`
+    ///
+    /// Only the rules for kinds that the language lacks are disabled.  See `check()`.
+    pub(crate) fn new(language: Language) -> Self {
+        let ids = named_kind_ids(language);
+        let mut symbols = vec![Symbol::Undefined; language.node_kind_count()];
+
+        for (kind, ids) in &ids {
+            for &id in ids {
+                symbols[id as usize] = Symbol::from_name(kind);
+            }
+        }
+
+        Self {
+            symbols,
+            missing: missing_kinds(&ids),
+        }
+    }
+
+    /// Runs `f` with the table for the Verilog grammar, which is built once per thread
+    ///
+    /// Fails if the grammar lacks a kind that a built-in rule matches on.
+    pub(crate) fn with_verilog<F, R>(f: F) -> Result<R>
+    where
+        F: FnOnce(&Kinds) -> Result<R>,
+    {
+        thread_local! {
+            static VERILOG: Kinds = Kinds::new(unsafe { tree_sitter_verilog() });
+        }
+
+        VERILOG.with(|kinds| {
+            kinds.check()?;
+            f(kinds)
+        })
+    }
+
+    /// Fails if the language lacks a kind that a built-in rule matches on
+    pub(crate) fn check(&self) -> Result<()> {
+        ensure!(
+            self.missing.is_empty(),
+            MissingRuleKinds {
+                kinds: self.missing.clone(),
+            }
+        );
+        Ok(())
+    }
+
+    pub(crate) fn missing(&self) -> &[&'static str] {
+        &self.missing
+    }
+
+    pub(crate) fn symbol(&self, node: Node<'_>) -> Symbol {
+        self.symbols
+            .get(node.kind_id() as usize)
+            .cloned()
+            .unwrap_or(Symbol::Undefined)
+    }
+}
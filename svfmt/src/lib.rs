@@ -1,10 +1,41 @@
-use std::fmt;
+use std::borrow::Cow;
 use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
 
 use log::debug;
-use snafu::{ensure, Backtrace, Snafu};
+use snafu::{ensure, Backtrace, OptionExt, Snafu};
 use tree_sitter::{Language, Node, Parser, Tree, TreeCursor};
 
+mod align;
+mod buffer;
+mod comments;
+mod diagnostic;
+mod doc;
+mod edits;
+mod encoding;
+mod idempotency;
+mod kinds;
+mod rules;
+mod session;
+mod source_map;
+mod syntax;
+
+use align::align_columns;
+use buffer::Buffer;
+use comments::{follows_comment, is_comment, Comments};
+pub use diagnostic::Location;
+pub use doc::Doc;
+pub use edits::{text_edits, TextEdit};
+pub use encoding::{split_byte_order_mark, Encoder, Encoding, BYTE_ORDER_MARK};
+pub use idempotency::{verify_idempotent, Instability};
+pub use kinds::missing_rule_kinds;
+use kinds::Kinds;
+pub use rules::{Context, Rule, Rules};
+pub use session::Session;
+pub use source_map::SourceMap;
+use syntax::Syntax;
+
 include!(concat!(env!("OUT_DIR"), "/symbols.rs"));
 
 #[derive(Debug, Snafu)]
@@ -14,15 +45,77 @@ pub enum Error {
     #[snafu(display("Could not set source language. {}", message))]
     LanguageError { message: String },
     #[snafu(display("Unexpected syntax tree.  Node does not contain expected child."))]
-    TreeError { backtrace: Backtrace },
+    TreeError {
+        location: Location,
+        backtrace: Backtrace,
+    },
     #[snafu(display("Unexpected syntax tree.  Invalid node child count."))]
-    InvalidCount { backtrace: Backtrace },
+    InvalidCount {
+        location: Location,
+        backtrace: Backtrace,
+    },
     #[snafu(display("Unexpected syntax tree.  Invalid node kind."))]
-    InvalidKind { backtrace: Backtrace },
+    InvalidKind {
+        location: Location,
+        backtrace: Backtrace,
+    },
+    #[snafu(display("Syntax error."))]
+    SyntaxError {
+        location: Location,
+        backtrace: Backtrace,
+    },
+    #[snafu(display("Could not read {}", path.display()))]
+    ReadError { path: PathBuf, source: io::Error },
+    #[snafu(display(
+        "Grammar does not define node kinds used by formatting rules: {}",
+        kinds.join(", ")
+    ))]
+    MissingRuleKinds { kinds: Vec<&'static str> },
+    #[snafu(display("Parsing was cancelled or timed out"))]
+    ParseCancelled,
+    #[snafu(display("Source is not valid {} at byte {}", encoding, offset))]
+    InvalidEncoding { encoding: Encoding, offset: usize },
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+impl Error {
+    /// Returns the source location of the node that caused a formatting error
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Error::TreeError { location, .. }
+            | Error::InvalidCount { location, .. }
+            | Error::InvalidKind { location, .. }
+            | Error::SyntaxError { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// Attaches the path of the file being formatted to the error location
+    pub fn with_path(mut self, path: &Path) -> Self {
+        match &mut self {
+            Error::TreeError { location, .. }
+            | Error::InvalidCount { location, .. }
+            | Error::InvalidKind { location, .. }
+            | Error::SyntaxError { location, .. } => location.path = Some(path.to_path_buf()),
+            _ => {}
+        }
+
+        self
+    }
+
+    /// Renders the error as a compiler-style diagnostic
+    ///
+    /// Errors with a location show the offending source line with the node underlined.  Other
+    /// errors render as their message alone.
+    pub fn render(&self, source: &str) -> String {
+        match self.location() {
+            Some(location) => location.render(&self.to_string(), source),
+            None => format!("error: {}\n", self),
+        }
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(source: io::Error) -> Self {
         Error::IoError { source }
@@ -48,125 +141,338 @@ pub fn parse<'a>(language: Language, source: &'a str) -> Result<Tree> {
     Ok(parser.parse(&source, None).unwrap())
 }
 
-pub fn format<'a, T>(f: &mut T, source: &'a str, tree: &Tree) -> Result<()>
-where
-    T: io::Write,
-{
-    let length = source.len() + source.len() / 2;
-    let mut b = Buffer::with_capacity(length);
-    Formatter::new(&source).format_node(&mut b, tree.root_node())?;
-    write!(f, "{}", b)?;
-    Ok(())
-}
+/// Formatting options
+///
+/// Options are added over time, so configs are built from the defaults with the setters below
+/// instead of struct literals, e.g. `Config::default().align_case_items(true)`.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Config {
+    /// The number of columns lines are wrapped at
+    pub line_width: usize,
 
-pub fn debug<'a, T>(f: &mut T, source: &'a str, tree: &Tree) -> Result<()>
-where
-    T: io::Write,
-{
-    writeln!(f, "{}", tree.root_node().to_sexp())?;
-    writeln!(f)?;
-    Formatter::new(&source).debug_walk(f, 0, &mut tree.walk())
-}
+    /// The number of spaces between code and a comment at the end of the same line
+    pub comment_spacing: usize,
 
-struct Buffer {
-    /// Holds the current content of the buffer.
-    ///
-    /// Clients use `push_str()` and `push()` to add content to the buffer.  Clients obtain the
-    /// content of the buffer via its Display implementation.
-    content: String,
+    /// Rules that replace the built-in rules for the node kinds they are registered for
+    pub rules: Rules,
 
-    /// The current length of the current line.
-    ///
-    /// As content is pushed into the buffer, the line_length is incremented for every character
-    /// added.  If a newline character is seen, the line length is reset to 0.
-    line_length: usize,
+    /// The encoding of sources passed to `format_bytes()`, or `None` to detect it
+    pub encoding: Option<Encoding>,
 
-    /// The current indent level in number of spaces.
-    indent: usize,
+    /// Whether a byte-order mark at the start of a source passed to `format_bytes()` is written
+    /// back to the output
+    pub keep_byte_order_mark: bool,
 
-    /// Indicates whether a blank line needs to be inserted in current indent.
-    ///
-    /// This gets reset anytime indentation changes and anytime a blank line is automatically
-    /// inserted.  It gets set by maybe_blank_line().  Clients should call maybe_blank_line()
-    /// at the end of a block.  This allows a blank line to be inserted between blocks in a given
-    /// scope but prevents lines from being inserted before the first block and after the last block.
-    insert_blank_line: bool,
+    /// Whether consecutive port declarations are aligned in columns of direction, type, packed
+    /// dimensions, name and unpacked dimensions
+    pub align_port_declarations: bool,
+
+    /// Whether the `=` before the defaults of consecutive parameters in a `#(...)` list are aligned
+    pub align_parameter_defaults: bool,
+
+    /// Whether the `(` of consecutive named port connections of an instance are aligned
+    pub align_port_connections: bool,
+
+    /// Whether the `=` of consecutive continuous assignments are aligned
+    pub align_continuous_assignments: bool,
+
+    /// Where the `begin` of a block that follows a header such as `always` or `if (...)` goes
+    pub begin_style: BeginStyle,
+
+    /// Whether `else` follows the `end` of the preceding block on the same line, as in
+    /// `end else begin`
+    pub else_after_end: bool,
+
+    /// Whether the `:` of consecutive case items that fit on a line are aligned
+    pub align_case_items: bool,
+
+    /// The most spaces aligning a column may add to a line.  Lines that need more are not aligned.
+    pub max_alignment_padding: usize,
 }
 
-impl Buffer {
-    fn with_capacity(capacity: usize) -> Self {
+impl Default for Config {
+    fn default() -> Self {
         Self {
-            content: String::with_capacity(capacity),
-            line_length: 0,
-            indent: 0,
-            insert_blank_line: false,
+            line_width: 80,
+            comment_spacing: 1,
+            rules: Rules::new(),
+            encoding: None,
+            keep_byte_order_mark: true,
+            align_port_declarations: false,
+            align_parameter_defaults: false,
+            align_port_connections: false,
+            align_continuous_assignments: false,
+            align_case_items: false,
+            max_alignment_padding: 16,
+            begin_style: BeginStyle::SameLine,
+            else_after_end: true,
         }
     }
+}
 
-    /// Adds a string to the buffer one character at a time
-    fn push_str(&mut self, s: &str) {
-        for c in s.chars() {
-            self.push(c);
-        }
+impl Config {
+    /// Sets `line_width`
+    pub fn line_width(mut self, line_width: usize) -> Self {
+        self.line_width = line_width;
+        self
     }
 
-    /// Adds a character to the buffer
-    ///
-    /// Updates line_length.  Adds indentation for new non-blank lines.
-    fn push(&mut self, c: char) {
-        if c == '\n' {
-            self.line_length = 0;
-        } else {
-            self.line_length += 1;
-        }
+    /// Sets `comment_spacing`
+    pub fn comment_spacing(mut self, comment_spacing: usize) -> Self {
+        self.comment_spacing = comment_spacing;
+        self
+    }
 
-        if c != '\n' && self.content.ends_with('\n') {
-            if self.insert_blank_line {
-                self.content.push('\n');
-                self.insert_blank_line = false;
-            }
-            self.push_indent();
-        }
+    /// Sets `rules`
+    pub fn rules(mut self, rules: Rules) -> Self {
+        self.rules = rules;
+        self
+    }
 
-        self.content.push(c);
+    /// Sets `encoding`
+    pub fn encoding(mut self, encoding: Option<Encoding>) -> Self {
+        self.encoding = encoding;
+        self
     }
 
-    /// Adds the current indentation level to the buffer
-    fn push_indent(&mut self) {
-        for _ in 0..self.indent {
-            self.content.push(' ');
-        }
+    /// Sets `keep_byte_order_mark`
+    pub fn keep_byte_order_mark(mut self, keep_byte_order_mark: bool) -> Self {
+        self.keep_byte_order_mark = keep_byte_order_mark;
+        self
+    }
+
+    /// Sets `align_port_declarations`
+    pub fn align_port_declarations(mut self, align_port_declarations: bool) -> Self {
+        self.align_port_declarations = align_port_declarations;
+        self
     }
 
-    fn increment_indent(&mut self) {
-        self.indent += 4;
-        self.insert_blank_line = false;
+    /// Sets `align_parameter_defaults`
+    pub fn align_parameter_defaults(mut self, align_parameter_defaults: bool) -> Self {
+        self.align_parameter_defaults = align_parameter_defaults;
+        self
     }
 
-    fn decrement_indent(&mut self) {
-        self.indent -= 4;
-        self.insert_blank_line = false;
+    /// Sets `align_port_connections`
+    pub fn align_port_connections(mut self, align_port_connections: bool) -> Self {
+        self.align_port_connections = align_port_connections;
+        self
+    }
+
+    /// Sets `align_continuous_assignments`
+    pub fn align_continuous_assignments(mut self, align_continuous_assignments: bool) -> Self {
+        self.align_continuous_assignments = align_continuous_assignments;
+        self
+    }
+
+    /// Sets `begin_style`
+    pub fn begin_style(mut self, begin_style: BeginStyle) -> Self {
+        self.begin_style = begin_style;
+        self
+    }
+
+    /// Sets `else_after_end`
+    pub fn else_after_end(mut self, else_after_end: bool) -> Self {
+        self.else_after_end = else_after_end;
+        self
+    }
+
+    /// Sets `align_case_items`
+    pub fn align_case_items(mut self, align_case_items: bool) -> Self {
+        self.align_case_items = align_case_items;
+        self
+    }
+
+    /// Sets `max_alignment_padding`
+    pub fn max_alignment_padding(mut self, max_alignment_padding: usize) -> Self {
+        self.max_alignment_padding = max_alignment_padding;
+        self
+    }
+}
+
+/// Placement of the `begin` of a block that follows a header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeginStyle {
+    /// `begin` goes at the end of the header line (K&R style)
+    SameLine,
+
+    /// `begin` goes on a line of its own, indented like the header
+    OwnLine,
+}
+
+/// Formats a tree parsed with `tree_sitter_verilog()`
+///
+/// Trees with syntax errors are not formatted.  The error points at the first one.  Use a
+/// `Session` to format trees of other languages or many sources.
+pub fn format<'a, T>(f: &mut T, source: &'a str, tree: &Tree) -> Result<()>
+where
+    T: io::Write,
+{
+    format_with_config(f, source, tree, &Config::default())
+}
+
+/// Formats like `format()` with the given options
+pub fn format_with_config<'a, T>(
+    f: &mut T,
+    source: &'a str,
+    tree: &Tree,
+    config: &Config,
+) -> Result<()>
+where
+    T: io::Write,
+{
+    Kinds::with_verilog(|kinds| format_into(Buffer::new(f), source, tree, config, kinds))?;
+    Ok(())
+}
+
+/// Formats like `format_with_config()` and also returns a map between positions in `source` and
+/// positions in the output
+pub fn format_with_source_map<T>(
+    f: &mut T,
+    source: &str,
+    tree: &Tree,
+    config: &Config,
+) -> Result<SourceMap>
+where
+    T: io::Write,
+{
+    let mappings = Kinds::with_verilog(|kinds| {
+        format_into(Buffer::with_source_map(f), source, tree, config, kinds)
+    })?;
+    Ok(SourceMap::new(mappings))
+}
+
+fn format_into<T>(
+    mut b: Buffer<T>,
+    source: &str,
+    tree: &Tree,
+    config: &Config,
+    kinds: &Kinds,
+) -> Result<Vec<source_map::Mapping>>
+where
+    T: io::Write,
+{
+    let root = tree.root_node();
+    // Rules expect well-formed trees and could drop the text of error nodes
+    ensure!(
+        !root.has_error(),
+        SyntaxError {
+            location: Location::new(first_error(root)),
+        }
+    );
+    let formatter = Formatter::new(source, root, config, kinds);
+
+    // Render one top-level item at a time so that only the document for the current item is held
+    // in memory
+    for child in root.children() {
+        let doc = formatter.format_node(child)?;
+        doc::render(&doc, &mut b, config.line_width);
     }
+    doc::render(
+        &formatter.format_dangling_comments(root),
+        &mut b,
+        config.line_width,
+    );
+
+    Ok(b.finish()?)
+}
 
-    fn maybe_blank_line(&mut self) {
-        self.insert_blank_line = true;
+/// Returns the innermost node that contains the first syntax error within `node`
+fn first_error(node: Node<'_>) -> Node<'_> {
+    match node.children().find(|child| child.has_error()) {
+        Some(child) => first_error(child),
+        None => node,
     }
 }
 
-impl fmt::Display for Buffer {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.content)
+/// Formats Verilog source in any supported encoding
+///
+/// The output is written in the encoding of the source.  A UTF-8 byte-order mark is removed
+/// before parsing and, unless `Config::keep_byte_order_mark` is false, written back to the output.
+pub fn format_bytes<T>(f: &mut T, source: &[u8], config: &Config) -> Result<()>
+where
+    T: io::Write,
+{
+    format_encoded(f, source, config, |f, source| {
+        let tree = parse(unsafe { tree_sitter_verilog() }, source)?;
+        format_with_config(f, source, &tree, config)
+    })
+}
+
+/// Decodes `source`, formats it with `format` and encodes the output
+fn format_encoded<T, F>(f: &mut T, source: &[u8], config: &Config, format: F) -> Result<()>
+where
+    T: io::Write,
+    F: FnOnce(&mut Encoder<&mut T>, &str) -> Result<()>,
+{
+    let (has_byte_order_mark, encoding, source) = split_source(source, config);
+
+    if has_byte_order_mark && config.keep_byte_order_mark {
+        f.write_all(BYTE_ORDER_MARK)?;
     }
+
+    let source = encoding.decode(source)?;
+    format(&mut encoding.writer(f), &source)
+}
+
+/// Decodes a source the way `format_bytes()` does
+///
+/// Returns the encoding of the source and its text without any byte-order mark.  The locations of
+/// formatting errors refer to this text.
+pub fn decode_source<'s>(source: &'s [u8], config: &Config) -> Result<(Encoding, Cow<'s, str>)> {
+    let (_, encoding, source) = split_source(source, config);
+    Ok((encoding, encoding.decode(source)?))
+}
+
+/// Returns whether `source` starts with a byte-order mark, its encoding and the rest of it
+fn split_source<'s>(source: &'s [u8], config: &Config) -> (bool, Encoding, &'s [u8]) {
+    // The first bytes of a declared Latin-1 source are characters, not a byte-order mark
+    let (has_byte_order_mark, source) = match config.encoding {
+        Some(Encoding::Latin1) => (false, source),
+        _ => split_byte_order_mark(source),
+    };
+    let encoding = if has_byte_order_mark {
+        Encoding::Utf8
+    } else {
+        config.encoding.unwrap_or_else(|| Encoding::detect(source))
+    };
+
+    (has_byte_order_mark, encoding, source)
+}
+
+pub fn debug<'a, T>(f: &mut T, source: &'a str, tree: &Tree) -> Result<()>
+where
+    T: io::Write,
+{
+    writeln!(f, "{}", tree.root_node().to_sexp())?;
+    writeln!(f)?;
+    let config = Config::default();
+    Kinds::with_verilog(|kinds| {
+        Formatter::new(&source, tree.root_node(), &config, kinds).debug_walk(f, 0, &mut tree.walk())
+    })
 }
 
+/// Keywords that start a port declaration
+const PORT_DIRECTIONS: &[&str] = &["input", "output", "inout", "ref"];
+
+/// A built-in formatting rule.  See `Formatter::builtin_rule()`.
+type BuiltinRule<'a> = fn(&Formatter<'a>, Node<'a>) -> Result<Doc<'a>>;
+
 struct Formatter<'a> {
     source: &'a [u8],
+    config: &'a Config,
+    kinds: &'a Kinds,
+    comments: Comments<'a>,
 }
 
 impl<'a> Formatter<'a> {
-    fn new(source: &'a str) -> Self {
+    fn new(source: &'a str, root: Node<'a>, config: &'a Config, kinds: &'a Kinds) -> Self {
         Self {
             source: source.as_bytes(),
+            config,
+            kinds,
+            comments: Comments::attach(root),
         }
     }
 
@@ -214,281 +520,1786 @@ impl<'a> Formatter<'a> {
         node.utf8_text(self.source).unwrap()
     }
 
-    fn format_terminals(&self, node: Node<'a>, sep: &str) -> String {
-        Terminals::new(node)
-            .map(|node| self.text(node))
-            .collect::<Vec<&str>>()
-            .join(sep)
+    fn location(&self, node: Node<'a>) -> Location {
+        Location::new(node)
     }
 
-    fn format_children(&self, buffer: &mut Buffer, node: Node<'a>) -> Result<()> {
-        for child in node.children() {
-            self.format_node(buffer, child)?;
-        }
+    /// Returns the child at `index` or a TreeError located at `node`
+    fn child(&self, node: Node<'a>, index: usize) -> Result<Node<'a>> {
+        node.child(index).context(TreeError {
+            location: self.location(node),
+        })
+    }
 
-        Ok(())
+    /// Wraps `node` in its typed wrapper or returns an InvalidKind error located at `node`
+    fn cast<T>(&self, node: Node<'a>) -> Result<T>
+    where
+        T: Syntax<'a>,
+    {
+        T::cast(node).context(InvalidKind {
+            location: self.location(node),
+        })
     }
 
-    fn format_node(&self, buffer: &mut Buffer, node: Node<'a>) -> Result<()> {
-        debug!("format_node() kind:{}", node.kind());
-        match Symbol::from(node.kind_id()) {
-            Symbol::FunctionDeclaration => self.format_function_declaration(buffer, node)?,
-            Symbol::ClassDeclaration => self.format_class_declaration(buffer, node)?,
-            Symbol::Expression => self.format_expression(buffer, node)?,
-            Symbol::JumpStatement => self.format_jump_statement(buffer, node)?,
-            Symbol::OperatorAssignment => self.format_operator_assignment(buffer, node)?,
-            Symbol::IntegerAtomType => {
-                buffer.push_str(self.text(node));
-                buffer.push_str(" ");
-            }
-            Symbol::SimpleIdentifier => buffer.push_str(self.text(node)),
-            Symbol::ListOfArgumentsParent => self.format_list_of_arguments(buffer, node)?,
-            Symbol::PrimaryLiteral => buffer.push_str(self.text(node)),
-            _ => self.format_children(buffer, node)?,
-        }
+    /// Returns a child looked up through a typed wrapper or a TreeError located at `parent`
+    fn expect<T>(&self, parent: Node<'a>, child: Option<T>) -> Result<T> {
+        child.context(TreeError {
+            location: self.location(parent),
+        })
+    }
 
-        Ok(())
+    /// Returns the source text of `node` as a document that is tracked by the source map
+    fn token(&self, node: Node<'a>) -> Doc<'a> {
+        Doc::source(self.text(node), node.start_byte())
+    }
+
+    fn format_terminals(&self, node: Node<'a>, sep: &'static str) -> Doc<'a> {
+        Doc::join(
+            Terminals::new(node).map(|node| self.token(node)),
+            Doc::text(sep),
+        )
     }
 
-    fn format_list_of_arguments(&self, buffer: &mut Buffer, node: Node<'a>) -> Result<()> {
-        buffer.push_str("(");
+    fn format_children(&self, node: Node<'a>) -> Result<Doc<'a>> {
         let children = node
             .children()
-            .filter(|node| node.is_named())
-            .identify_last();
+            .map(|child| self.format_node(child))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Doc::concat(children))
+    }
+
+    /// Formats a node along with the comments attached to it
+    fn format_node(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        debug!("format_node() kind:{}", node.kind());
 
-        for (last, child) in children {
-            self.format_node(buffer, child)?;
+        // Comments are emitted by the node they are attached to
+        if is_comment(node) {
+            return Ok(Doc::Nil);
+        }
+
+        Ok(Doc::concat(vec![
+            self.format_leading_comments(node),
+            self.format_kind(node)?,
+            self.format_trailing_comments(node),
+            self.format_dangling_comments(node),
+        ]))
+    }
+
+    /// Formats a node using the rule registered for its kind or the built-in rule if there is none
+    fn format_kind(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        match self.config.rules.get(node.kind()) {
+            Some(rule) => rule(node, &Context::new(self)),
+            None => self.format_builtin(node),
+        }
+    }
+
+    fn format_builtin(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        match self.builtin_rule(node) {
+            Some(rule) => rule(self, node),
+            None => self.format_children(node),
+        }
+    }
 
-            if !last {
-                buffer.push_str(", ");
+    /// Returns the built-in rule for the kind of `node`, if there is one
+    fn builtin_rule(&self, node: Node<'a>) -> Option<BuiltinRule<'a>> {
+        let rule: BuiltinRule<'a> = match self.kinds.symbol(node) {
+            Symbol::FunctionDeclaration => Self::format_function_declaration,
+            Symbol::ClassDeclaration => Self::format_class_declaration,
+            Symbol::ModuleDeclaration | Symbol::InterfaceDeclaration => {
+                Self::format_module_declaration
+            }
+            Symbol::ModuleAnsiHeader
+            | Symbol::ModuleNonansiHeader
+            | Symbol::InterfaceAnsiHeader
+            | Symbol::InterfaceNonansiHeader => Self::format_module_header,
+            Symbol::ParameterPortList => Self::format_parameter_port_list,
+            Symbol::ModuleInstantiation => Self::format_module_instantiation,
+            Symbol::ContinuousAssign => Self::format_continuous_assign,
+            Symbol::AlwaysConstruct | Symbol::InitialConstruct | Symbol::FinalConstruct => {
+                Self::format_procedural_block
+            }
+            Symbol::ProceduralTimingControlStatement => {
+                Self::format_procedural_timing_control_statement
+            }
+            Symbol::EventControl => Self::format_event_control,
+            Symbol::SeqBlock => Self::format_seq_block,
+            Symbol::ConditionalStatement => Self::format_conditional_statement,
+            Symbol::CaseStatement => Self::format_case_statement,
+            Symbol::CaseItem | Symbol::CaseInsideItem => Self::format_case_item,
+            Symbol::LoopStatement => Self::format_loop_statement,
+            Symbol::ParameterValueAssignment => Self::format_parameter_value_assignment,
+            Symbol::HierarchicalInstance => Self::format_hierarchical_instance,
+            Symbol::NamedPortConnection | Symbol::NamedParameterAssignment => {
+                Self::format_named_connection
             }
+            Symbol::ListOfPortDeclarations | Symbol::ListOfPorts => Self::format_list_of_ports,
+            Symbol::Expression => Self::format_expression,
+            Symbol::JumpStatement => Self::format_jump_statement,
+            Symbol::OperatorAssignment => Self::format_operator_assignment,
+            Symbol::IntegerAtomType => Self::format_integer_atom_type,
+            Symbol::SimpleIdentifier => Self::format_token,
+            Symbol::ListOfArgumentsParent => Self::format_list_of_arguments,
+            Symbol::PrimaryLiteral => Self::format_token,
+            Symbol::FunctionStatementOrNull => Self::format_function_statement_or_null,
+            _ => return None,
+        };
+
+        Some(rule)
+    }
+
+    /// Indicates whether a registered or built-in rule formats `node`
+    fn has_rule(&self, node: Node<'a>) -> bool {
+        self.config.rules.get(node.kind()).is_some() || self.builtin_rule(node).is_some()
+    }
+
+    /// Formats an item of a block, e.g. a module item
+    ///
+    /// Wrappers around a single item are looked through.  Items that no rule formats keep their
+    /// source text so that constructs without a rule yet are left as they are instead of dropped.
+    fn format_item(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        if self.has_rule(node) {
+            return self.format_node(node);
         }
-        buffer.push_str(")");
-        Ok(())
+
+        let mut children = node.children().filter(|&child| !is_comment(child));
+        let item = match (children.next(), children.next()) {
+            (Some(child), None) if child.is_named() => self.format_item(child)?,
+            _ => self.format_verbatim(node),
+        };
+
+        Ok(Doc::concat(vec![
+            self.format_leading_comments(node),
+            item,
+            self.format_trailing_comments(node),
+            self.format_dangling_comments(node),
+        ]))
     }
 
-    fn format_expression(&self, buffer: &mut Buffer, node: Node<'a>) -> Result<()> {
-        if node.child_count() == 3 {
-            // Binary expression
-            let left = node.child(0).unwrap();
-            let operator = node.child(1).unwrap();
-            let right = node.child(2).unwrap();
-
-            self.format_node(buffer, left)?;
-            buffer.push_str(" ");
-            buffer.push_str(self.text(operator));
-            buffer.push_str(" ");
-            self.format_node(buffer, right)?;
-        } else {
-            ensure!(node.child_count() == 1, InvalidCount);
+    /// Returns the item a wrapper holds, looking through wrappers the same way as `format_item()`
+    fn unwrap_item(&self, node: Node<'a>) -> Node<'a> {
+        let mut node = node;
 
-            self.format_node(buffer, node.child(0).unwrap())?;
+        while !self.has_rule(node) {
+            let mut children = node.children().filter(|&child| !is_comment(child));
+            match (children.next(), children.next()) {
+                (Some(child), None) if child.is_named() => node = child,
+                _ => break,
+            }
         }
 
-        Ok(())
+        node
     }
 
-    fn format_jump_statement(&self, buffer: &mut Buffer, node: Node<'a>) -> Result<()> {
-        let jump_type = node.child(0).unwrap();
+    /// Returns the source text of `node` as it is
+    ///
+    /// Lines after the first are re-indented relative to the current indentation.  Comments within
+    /// the node are part of the text.
+    fn format_verbatim(&self, node: Node<'a>) -> Doc<'a> {
+        for comment in self.comments.remaining(node) {
+            self.comments.take(comment);
+        }
 
-        buffer.push_str(self.text(jump_type));
-        if node.child_count() == 3 {
-            buffer.push_str(" ");
-            let expression = node.child(1).unwrap();
-            self.format_expression(buffer, expression)?;
+        let text = self.text(node);
+        if !text.contains('\n') {
+            return self.token(node);
         }
 
-        Ok(())
+        // Each line is a separate piece of source text so that the source map stays accurate
+        let column = node.start_position().column;
+        let mut offset = node.start_byte();
+        let mut docs = Vec::new();
+
+        for (i, line) in text.split('\n').enumerate() {
+            let indent = if i == 0 {
+                0
+            } else {
+                docs.push(Doc::HardLine);
+                let indent = line.len() - line.trim_start_matches(&[' ', '\t'][..]).len();
+                indent.min(column)
+            };
+            docs.push(Doc::source(&line[indent..], offset + indent));
+
+            offset += line.len() + 1;
+        }
+
+        Doc::concat(docs)
     }
 
-    fn format_operator_assignment(&self, buffer: &mut Buffer, node: Node<'a>) -> Result<()> {
-        ensure!(node.child_count() == 3, InvalidCount);
+    fn format_token(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        Ok(self.token(node))
+    }
 
-        let lvalue = node.child(0).unwrap();
-        let assignment_operator = node.child(1).unwrap();
-        let expression = node.child(2).unwrap();
+    fn format_integer_atom_type(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        Ok(Doc::concat(vec![self.token(node), Doc::text(" ")]))
+    }
 
-        buffer.push_str(self.text(lvalue));
-        buffer.push(' ');
-        buffer.push_str(self.text(assignment_operator));
-        buffer.push(' ');
-        self.format_expression(buffer, expression)?;
+    /// Returns the text of a comment without trailing whitespace
+    ///
+    /// Only ASCII whitespace is trimmed.  Other characters are left as they are, e.g. a Latin-1
+    /// non-breaking space.
+    fn comment_text(&self, comment: Node<'a>) -> &'a str {
+        self.text(comment)
+            .trim_end_matches(|c: char| c.is_ascii_whitespace())
+    }
 
-        Ok(())
+    fn format_comment(&self, comment: Node<'a>) -> Doc<'a> {
+        Doc::source(self.comment_text(comment), comment.start_byte())
     }
 
-    fn format_class_declaration(&self, buffer: &mut Buffer, node: Node<'a>) -> Result<()> {
-        let mut class_item_seen = false;
+    fn is_line_comment(&self, comment: Node<'a>) -> bool {
+        self.text(comment).starts_with("//")
+    }
 
-        buffer.push_str("class ");
-        for child in node.children() {
-            debug!("format_class_declaration() child:{}", child.kind());
-            match Symbol::from(child.kind_id()) {
-                Symbol::ClassIdentifier => {
-                    buffer.push_str(&self.format_terminals(child, " "));
-                }
-                Symbol::ClassItem => {
-                    if !class_item_seen {
-                        buffer.push_str(";\n");
-                        buffer.increment_indent();
-                        class_item_seen = true;
-                    }
+    /// Indicates whether only whitespace precedes `node` on its line
+    fn starts_line(&self, node: Node<'a>) -> bool {
+        self.source[..node.start_byte()]
+            .iter()
+            .rev()
+            .take_while(|&&c| c != b'\n')
+            .all(|c| c.is_ascii_whitespace())
+    }
+
+    /// Indicates whether only whitespace follows `node` on its line
+    fn ends_line(&self, node: Node<'a>) -> bool {
+        self.source[node.end_byte()..]
+            .iter()
+            .take_while(|&&c| c != b'\n')
+            .all(|c| c.is_ascii_whitespace())
+    }
+
+    /// Formats the comments that precede `node`
+    ///
+    /// Each comment goes on its own line unless it is a block comment on the same line as what
+    /// follows it.  A single blank line is preserved between a comment and what follows it.
+    fn format_leading_comments(&self, node: Node<'a>) -> Doc<'a> {
+        let comments = self.comments.leading(node);
+        let mut docs = Vec::new();
+
+        for (i, &comment) in comments.iter().enumerate() {
+            if !self.comments.take(comment) {
+                continue;
+            }
+
+            let next = comments.get(i + 1).cloned().unwrap_or(node);
+            let rows = next.start_position().row - comment.end_position().row;
 
-                    self.format_node(buffer, child)?;
+            docs.push(self.format_comment(comment));
+            if rows == 0 && !self.is_line_comment(comment) {
+                docs.push(Doc::text(" "));
+            } else {
+                docs.push(Doc::HardLine);
+                if rows > 1 {
+                    docs.push(Doc::HardLine);
                 }
-                _ => {}
             }
         }
 
-        buffer.decrement_indent();
-        buffer.push_str("endclass\n");
-        buffer.maybe_blank_line();
-        Ok(())
+        Doc::concat(docs)
     }
 
-    fn format_function_declaration(&self, buffer: &mut Buffer, node: Node<'a>) -> Result<()> {
-        ensure!(node.child_count() == 2, InvalidCount);
+    /// Formats the comments that follow `node`
+    ///
+    /// Comments that start on the line `node` ends on stay on that line.
+    fn format_trailing_comments(&self, node: Node<'a>) -> Doc<'a> {
+        let mut docs = Vec::new();
+        let mut prev = node;
+
+        for &comment in self.comments.trailing(node) {
+            if self.comments.take(comment) {
+                if comment.start_position().row == prev.end_position().row {
+                    docs.push(self.format_end_of_line_comment(comment));
+                } else {
+                    docs.push(self.format_own_line_comment(prev, comment));
+                }
+                prev = comment;
+            }
+        }
 
-        let keyword = node.child(0).unwrap();
-        let body = node.child(1).unwrap();
+        Doc::concat(docs)
+    }
 
-        ensure!(keyword.kind() == "function", InvalidKind);
-        ensure!(Symbol::from(body.kind_id()) == Symbol::FunctionBodyDeclaration, InvalidKind);
+    /// Formats a comment that follows code on the same line
+    ///
+    /// Block comments followed by more code stay inline.  All others are moved to the end of the
+    /// line.
+    fn format_end_of_line_comment(&self, comment: Node<'a>) -> Doc<'a> {
+        if self.is_line_comment(comment) || self.ends_line(comment) {
+            Doc::concat(vec![
+                Doc::line_suffix(" ".repeat(self.config.comment_spacing)),
+                Doc::line_suffix_source(self.comment_text(comment), comment.start_byte()),
+            ])
+        } else {
+            Doc::concat(vec![Doc::text(" "), self.format_comment(comment)])
+        }
+    }
 
-        buffer.push_str("function ");
+    /// Formats a comment on a line of its own
+    ///
+    /// The comment is preceded by a blank line if there was one between `prev` and the comment.
+    fn format_own_line_comment(&self, prev: Node<'a>, comment: Node<'a>) -> Doc<'a> {
+        let mut docs = vec![Doc::LineStart];
 
-        for child in body.children() {
-            debug!("format_function_declaration() child:{}", child.kind());
-            match Symbol::from(child.kind_id()) {
-                Symbol::FunctionDataTypeOrImplicit1 => {
-                    buffer.push_str(&self.format_terminals(child, " "));
-                    buffer.push_str(" ");
-                }
-                Symbol::FunctionIdentifier => {
-                    buffer.push_str(&self.format_terminals(child, " "));
-                }
-                Symbol::TfPortList => {
-                    &self.format_tf_port_list(buffer, child)?;
-                    buffer.push_str("\n");
-                }
-                Symbol::FunctionStatementOrNull => {
-                    buffer.increment_indent();
-                    self.format_function_statement_or_null(buffer, child)?;
-                    buffer.decrement_indent();
+        if comment.start_position().row > prev.end_position().row + 1 {
+            docs.push(Doc::HardLine);
+        }
+        docs.push(self.format_comment(comment));
+        docs.push(Doc::HardLine);
+
+        Doc::concat(docs)
+    }
+
+    /// Formats the comments between `node` and its previous sibling
+    ///
+    /// Used for keywords such as `else` that a rule puts on a line of their own when comments
+    /// precede them.  The comments are attached to the nodes around the keyword, which may be
+    /// formatted after it.
+    fn format_comments_before(&self, node: Node<'a>) -> Doc<'a> {
+        let mut comments = Vec::new();
+        let mut prev = node.prev_sibling();
+
+        while let Some(comment) = prev.filter(|&prev| is_comment(prev)) {
+            comments.push(comment);
+            prev = comment.prev_sibling();
+        }
+
+        let mut prev = match prev {
+            Some(prev) => prev,
+            None => return Doc::Nil,
+        };
+        let mut docs = Vec::new();
+
+        for comment in comments.into_iter().rev() {
+            if self.comments.take(comment) {
+                if comment.start_position().row == prev.end_position().row {
+                    docs.push(self.format_end_of_line_comment(comment));
+                } else {
+                    docs.push(self.format_own_line_comment(prev, comment));
                 }
-                Symbol::Comment => {
-                    buffer.increment_indent();
-                    if self.blank_lines_after_previous_function_item(child) > 0 {
-                        buffer.push('\n');
-                    }
-                    buffer.push_str(self.text(child));
-                    buffer.push_str("\n");
-                    buffer.decrement_indent();
+            }
+            prev = comment;
+        }
+
+        Doc::concat(docs)
+    }
+
+    /// Formats the comments within `node` that no rule has emitted
+    ///
+    /// This covers dangling comments as well as comments attached to nodes that a rule formats
+    /// without recursing into (e.g. via `format_terminals()`) so that no comment is dropped.
+    fn format_dangling_comments(&self, node: Node<'a>) -> Doc<'a> {
+        let dangling = self.comments.dangling(node).iter().cloned();
+        let mut docs = Vec::new();
+
+        for comment in dangling.chain(self.comments.remaining(node)) {
+            if self.comments.take(comment) {
+                if self.starts_line(comment) {
+                    docs.push(self.format_own_line_comment(comment, comment));
+                } else {
+                    docs.push(self.format_end_of_line_comment(comment));
                 }
-                _ => {}
             }
         }
 
-        buffer.push_str("endfunction\n");
-        buffer.maybe_blank_line();
-        Ok(())
+        Doc::concat(docs)
     }
 
-    fn to_line_buffer<F>(&self, f: F, node: Node<'a>) -> Result<String>
-    where
-        F: Fn(&Self, &mut Buffer, Node<'a>) -> Result<()>,
-    {
-        let mut b = Buffer::with_capacity(1024);
-        f(self, &mut b, node)?;
-        Ok(b.to_string())
+    /// Formats a parenthesized, comma separated list
+    ///
+    /// The list stays on the current line if it fits.  Otherwise each item goes on a line of its
+    /// own and the closing parenthesis goes on the line after the last item.
+    fn format_parenthesized_list(&self, items: Vec<Doc<'a>>, close: &'a str) -> Doc<'a> {
+        Doc::group(Doc::concat(vec![
+            Doc::text("("),
+            Doc::indent(Doc::concat(vec![
+                Doc::SoftLine,
+                Doc::join(items, Doc::concat(vec![Doc::text(","), Doc::Line])),
+            ])),
+            Doc::SoftLine,
+            Doc::text(close),
+        ]))
     }
 
-    fn format_tf_port_list(&self, buffer: &mut Buffer, node: Node<'a>) -> Result<()> {
-        let children = node
+    fn format_list_of_arguments(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let arguments = node
             .children()
-            .filter(|child| child.is_named())
-            .map(|child| self.to_line_buffer(Self::format_node, child))
+            .filter(|&node| node.is_named() && !is_comment(node))
+            .map(|child| self.format_node(child))
             .collect::<Result<Vec<_>>>()?;
 
-        let single_line = format!("({});", children.join(", "));
+        Ok(self.format_parenthesized_list(arguments, ")"))
+    }
+
+    fn format_expression(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let expression: syntax::Expression = self.cast(node)?;
 
-        if buffer.line_length + single_line.len() <= 80 {
-            buffer.push_str(&single_line);
+        if let [left, right] = expression.expression_list()[..] {
+            // Binary expression
+            let operator = self.expect(node, left.node().next_sibling())?;
+
+            // Continuation lines line up with the start of the expression
+            Ok(Doc::align(Doc::group(Doc::concat(vec![
+                self.format_node(left.node())?,
+                Doc::text(" "),
+                self.token(operator),
+                Doc::Line,
+                self.format_node(right.node())?,
+            ]))))
         } else {
-            buffer.push_str("(\n");
-            buffer.increment_indent();
-            for (last, child) in children.iter().identify_last() {
-                buffer.push_str(&child);
-                if !last {
-                    buffer.push_str(",");
-                }
-                buffer.push_str("\n");
+            let mut children = node.children().filter(|&child| !is_comment(child));
+            match (children.next(), children.next()) {
+                // An operand
+                (Some(operand), None) => self.format_node(operand),
+                // Other expressions, e.g. unary ones, have no rule yet
+                _ => Ok(self.format_inline(node)),
             }
-            buffer.decrement_indent();
-            buffer.push_str(");");
         }
-
-        Ok(())
     }
 
-    fn format_function_statement_or_null(&self, buffer: &mut Buffer, node: Node<'a>) -> Result<()> {
-        ensure!(node.child_count() == 1, InvalidCount);
+    fn format_jump_statement(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let statement: syntax::JumpStatement = self.cast(node)?;
+        let jump_type = self.child(node, 0)?;
+        let mut docs = vec![self.token(jump_type)];
 
-        if self.blank_lines_after_previous_function_item(node) > 0 {
-            buffer.push('\n');
+        if let Some(expression) = statement.expression() {
+            docs.push(Doc::text(" "));
+            docs.push(self.format_expression(expression.node())?);
         }
 
-        self.format_children(buffer, node)?;
-        buffer.push_str(";\n");
-        Ok(())
+        Ok(Doc::concat(docs))
     }
 
-    fn blank_lines_after_previous_function_item(&self, node: Node<'a>) -> usize {
-        let prev = node.prev_sibling();
-
-        if let Some(prev) = prev {
-            // Need to check the previous symbol type because the grammar mixes function items with
-            // function head items.
-            match Symbol::from(prev.kind_id()) {
-                Symbol::FunctionStatementOrNull
-                | Symbol::Comment => {
-                    let difference = node.start_position().row - prev.end_position().row;
-
-                    if difference == 0 {
-                        0
-                    } else {
-                        difference - 1
-                    }
-                }
-                _ => 0,
-            }
-        } else {
-            0
+    fn format_operator_assignment(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let assignment: syntax::OperatorAssignment = self.cast(node)?;
+        let lvalue = self.expect(node, assignment.variable_lvalue())?;
+        let assignment_operator = self.expect(node, assignment.assignment_operator())?;
+        let expression = self.expect(node, assignment.expression())?;
+
+        Ok(Doc::concat(vec![
+            self.token(lvalue.node()),
+            Doc::text(" "),
+            self.token(assignment_operator.node()),
+            Doc::text(" "),
+            self.format_expression(expression.node())?,
+        ]))
+    }
+
+    fn format_class_declaration(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let declaration: syntax::ClassDeclaration = self.cast(node)?;
+        let identifier = self.expect(node, declaration.class_identifier())?;
+        let mut docs = vec![
+            Doc::text("class "),
+            self.format_terminals(identifier.node(), " "),
+        ];
+
+        if let Some(parameters) = declaration.parameter_port_list() {
+            docs.push(Doc::text(" "));
+            docs.push(self.format_node(parameters.node())?);
         }
+
+        let items = declaration
+            .class_item_list()
+            .into_iter()
+            .map(|item| self.format_node(item.node()))
+            .collect::<Result<Vec<_>>>()?;
+
+        docs.push(Doc::text(";"));
+        docs.push(Doc::HardLine);
+        docs.push(Doc::indent(Doc::concat(items)));
+        docs.push(Doc::text("endclass"));
+        docs.push(Doc::HardLine);
+        docs.push(Doc::MaybeBlankLine);
+        Ok(Doc::concat(docs))
     }
-}
 
-/// Iterator struct for iterating over all terminal nodes
-struct Terminals<'a> {
-    index: usize,
-    terminals: Vec<Node<'a>>,
-}
+    fn format_function_declaration(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let declaration: syntax::FunctionDeclaration = self.cast(node)?;
+        let body = self.expect(node, declaration.function_body_declaration())?;
+        let identifier = self.expect(body.node(), body.function_identifier())?;
 
-impl<'a> Terminals<'a> {
-    fn new(node: Node<'a>) -> Self {
-        let mut terminals = Vec::new();
+        let mut docs = vec![Doc::text("function ")];
 
-        Self::collect_terminals(node, &mut terminals);
+        if let Some(data_type) = body.function_data_type_or_implicit1() {
+            docs.push(self.format_terminals(data_type.node(), " "));
+            docs.push(Doc::text(" "));
+        }
+        docs.push(self.format_terminals(identifier.node(), " "));
 
-        Terminals {
-            index: 0,
-            terminals,
+        match body.tf_port_list() {
+            Some(ports) => {
+                docs.push(self.format_tf_port_list(ports.node())?);
+                docs.push(Doc::HardLine);
+                docs.push(Doc::indent(self.format_trailing_comments(ports.node())));
+            }
+            None => {
+                docs.push(Doc::text(";"));
+                docs.push(Doc::HardLine);
+            }
+        }
+
+        for statement in body.function_statement_or_null_list() {
+            let statement = statement.node();
+            let mut item = Vec::new();
+            if self.blank_lines_after_previous_function_item(statement) > 0 {
+                item.push(Doc::HardLine);
+            }
+            item.push(self.format_node(statement)?);
+            docs.push(Doc::indent(Doc::concat(item)));
         }
+
+        docs.push(Doc::text("endfunction"));
+        docs.push(Doc::HardLine);
+        docs.push(Doc::MaybeBlankLine);
+        Ok(Doc::concat(docs))
     }
 
-    fn collect_terminals(node: Node<'a>, terminals: &mut Vec<Node<'a>>) {
+    /// Formats a module or interface declaration
+    fn format_module_declaration(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let mut docs = Vec::new();
+        let mut items = Vec::new();
+        let mut end_seen = false;
+
         for child in node.children() {
+            debug!("format_module_declaration() child:{}", child.kind());
+            if is_comment(child) {
+                continue;
+            }
+
+            match self.kinds.symbol(child) {
+                Symbol::ModuleAnsiHeader
+                | Symbol::ModuleNonansiHeader
+                | Symbol::InterfaceAnsiHeader
+                | Symbol::InterfaceNonansiHeader => {
+                    docs.push(self.format_node(child)?);
+                    docs.push(Doc::HardLine);
+                }
+                _ if child.kind() == "endmodule" || child.kind() == "endinterface" => {
+                    docs.push(self.format_module_items(&items)?);
+                    docs.push(Doc::LineStart);
+                    docs.push(self.token(child));
+                    end_seen = true;
+                }
+                // The label after `endmodule :`
+                _ if end_seen && child.is_named() => {
+                    docs.push(Doc::text(" : "));
+                    docs.push(self.token(child));
+                }
+                _ if !end_seen && child.is_named() => items.push(child),
+                _ => {}
+            }
+        }
+
+        docs.push(Doc::HardLine);
+        docs.push(Doc::MaybeBlankLine);
+        Ok(Doc::concat(docs))
+    }
+
+    /// Formats the items of a module or interface, each on lines of its own
+    fn format_module_items(&self, items: &[Node<'a>]) -> Result<Doc<'a>> {
+        let formatted =
+            self.format_aligned_items(items, ";", self.config.max_alignment_padding, |item| {
+                let cells = if self.config.align_port_declarations && self.is_port_declaration(item)
+                {
+                    self.port_declaration_cells(item)
+                } else if self.config.align_continuous_assignments {
+                    self.continuous_assign_cells(self.unwrap_item(item))
+                } else {
+                    None
+                };
+                Ok(cells)
+            })?;
+
+        let mut docs = Vec::new();
+        for (&item, doc) in items.iter().zip(formatted) {
+            let mut item_docs = Vec::new();
+            if self.blank_lines_before(item) > 0 {
+                item_docs.push(Doc::MaybeBlankLine);
+            }
+            item_docs.push(doc);
+            item_docs.push(Doc::LineStart);
+            docs.push(Doc::indent(Doc::concat(item_docs)));
+        }
+
+        Ok(Doc::concat(docs))
+    }
+
+    /// Formats the part of a module or interface declaration up to and including the `;` after the
+    /// ports
+    fn format_module_header(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let mut docs = Vec::new();
+        let mut ports_seen = false;
+
+        for child in node.children() {
+            debug!("format_module_header() child:{}", child.kind());
+            if is_comment(child) || child.kind() == ";" {
+                continue;
+            }
+
+            match self.kinds.symbol(child) {
+                Symbol::ListOfPortDeclarations | Symbol::ListOfPorts => {
+                    let prev = child
+                        .prev_named_sibling()
+                        .map(|prev| self.kinds.symbol(prev));
+                    if prev == Some(Symbol::ParameterPortList) {
+                        docs.push(Doc::text(" "));
+                    }
+                    docs.push(self.format_node(child)?);
+                    ports_seen = true;
+                }
+                _ => {
+                    if !docs.is_empty() {
+                        docs.push(Doc::text(" "));
+                    }
+                    if child.is_named() {
+                        docs.push(self.format_item(child)?);
+                    } else {
+                        docs.push(self.token(child));
+                    }
+                }
+            }
+        }
+
+        // The port list includes the `;`
+        if !ports_seen {
+            docs.push(Doc::text(";"));
+        }
+
+        Ok(Doc::concat(docs))
+    }
+
+    /// Formats the ports of a module header followed by the `;` that ends the header
+    ///
+    /// The ports stay on the header line if they fit.  Otherwise each goes on a line of its own.
+    fn format_list_of_ports(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let ports = node
+            .children()
+            .filter(|&child| child.is_named() && !is_comment(child))
+            .collect::<Vec<_>>();
+        let ports = if self.config.align_port_declarations {
+            self.format_aligned_items(&ports, "", self.config.max_alignment_padding, |port| {
+                Ok(self.port_declaration_cells(port))
+            })?
+        } else {
+            ports
+                .into_iter()
+                .map(|port| self.format_item(port))
+                .collect::<Result<Vec<_>>>()?
+        };
+
+        if ports.is_empty() {
+            Ok(Doc::text("();"))
+        } else {
+            Ok(self.format_parenthesized_list(ports, ");"))
+        }
+    }
+
+    /// Formats the `#(...)` parameters of a module, interface or class
+    ///
+    /// The parameters stay on the current line if they fit.  Otherwise each goes on a line of its
+    /// own.
+    fn format_parameter_port_list(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let mut parameters = Vec::new();
+        for child in node.children() {
+            if !child.is_named() || is_comment(child) {
+                continue;
+            }
+
+            // Parameters without the `parameter` keyword come as a comma separated list
+            if child.children().any(|grandchild| grandchild.kind() == ",") {
+                parameters.extend(
+                    child
+                        .children()
+                        .filter(|&grandchild| grandchild.is_named() && !is_comment(grandchild)),
+                );
+            } else {
+                parameters.push(child);
+            }
+        }
+
+        if parameters.is_empty() {
+            return Ok(Doc::text("#()"));
+        }
+
+        // Cells are always used for their spacing and only padded if defaults are aligned
+        let max_padding = if self.config.align_parameter_defaults {
+            self.config.max_alignment_padding
+        } else {
+            0
+        };
+        let parameters = self.format_aligned_items(&parameters, "", max_padding, |parameter| {
+            Ok(self.parameter_cells(parameter))
+        })?;
+
+        Ok(Doc::concat(vec![
+            Doc::text("#"),
+            self.format_parenthesized_list(parameters, ")"),
+        ]))
+    }
+
+    /// Splits a parameter declaration into cells for everything before the `=` and for the `=` and
+    /// the default
+    ///
+    /// Returns `None` for declarations of several parameters and declarations with comments inside.
+    fn parameter_cells(&self, node: Node<'a>) -> Option<Vec<Doc<'a>>> {
+        if !self.comments.remaining(node).is_empty() {
+            return None;
+        }
+
+        let mut atoms = Vec::new();
+        self.declaration_atoms(node, &mut atoms);
+        if atoms.iter().any(|atom| atom.kind() == ",") {
+            return None;
+        }
+
+        match atoms.iter().position(|atom| atom.kind() == "=") {
+            Some(equals) => Some(vec![
+                self.collapsed_text(&atoms[..equals]),
+                Doc::concat(vec![
+                    Doc::text("= "),
+                    self.collapsed_text(&atoms[equals + 1..]),
+                ]),
+            ]),
+            None => Some(vec![self.collapsed_text(&atoms), Doc::Nil]),
+        }
+    }
+
+    /// Indicates whether a module item declares ports, i.e. starts with a port direction
+    fn is_port_declaration(&self, item: Node<'a>) -> bool {
+        let mut first = item;
+        while let Some(child) = first.child(0) {
+            first = child;
+        }
+
+        PORT_DIRECTIONS.contains(&first.kind())
+    }
+
+    /// Splits a port declaration into cells for its direction, type, packed dimensions, name and
+    /// unpacked dimensions
+    ///
+    /// A default value is part of the unpacked dimensions cell.  A declaration of several ports
+    /// has the names and everything after them in the name cell.  Returns `None` for declarations
+    /// that don't fit these columns, e.g. explicit ports or declarations with comments inside.
+    fn port_declaration_cells(&self, node: Node<'a>) -> Option<Vec<Doc<'a>>> {
+        if !self.comments.remaining(node).is_empty() {
+            return None;
+        }
+
+        let mut atoms = Vec::new();
+        self.declaration_atoms(node, &mut atoms);
+        if atoms.last().map(Node::kind) == Some(";") {
+            atoms.pop();
+        }
+        if atoms.iter().any(|atom| atom.kind() == "(") {
+            return None;
+        }
+
+        let is_dimension = |atom: Node<'a>| {
+            matches!(
+                self.kinds.symbol(atom),
+                Symbol::PackedDimension | Symbol::UnpackedDimension
+            )
+        };
+
+        // The name is the last identifier before any unpacked dimensions, default or other names
+        let end = atoms
+            .iter()
+            .position(|&atom| {
+                self.kinds.symbol(atom) == Symbol::UnpackedDimension
+                    || atom.kind() == "="
+                    || atom.kind() == ","
+            })
+            .unwrap_or(atoms.len());
+        let name = atoms[..end]
+            .iter()
+            .rposition(|&atom| atom.is_named() && !is_dimension(atom))?;
+
+        let direction = match atoms.first() {
+            Some(atom) if PORT_DIRECTIONS.contains(&atom.kind()) => 1,
+            _ => 0,
+        };
+        let packed = atoms[direction..name]
+            .iter()
+            .rposition(|&atom| self.kinds.symbol(atom) != Symbol::PackedDimension)
+            .map_or(direction, |index| direction + index + 1);
+
+        let (name_cell, unpacked_cell) = match atoms.get(name + 1) {
+            Some(next) if next.kind() == "," => (self.collapsed_text(&atoms[name..]), Doc::Nil),
+            _ => (
+                self.token(atoms[name]),
+                self.collapsed_text(&atoms[name + 1..]),
+            ),
+        };
+
+        Some(vec![
+            self.collapsed_text(&atoms[..direction]),
+            self.collapsed_text(&atoms[direction..packed]),
+            self.collapsed_text(&atoms[packed..name]),
+            name_cell,
+            unpacked_cell,
+        ])
+    }
+
+    /// Collects the tokens of a declaration, with dimensions and identifiers kept whole
+    fn declaration_atoms(&self, node: Node<'a>, atoms: &mut Vec<Node<'a>>) {
+        for child in node.children() {
+            match self.kinds.symbol(child) {
+                Symbol::PackedDimension | Symbol::UnpackedDimension | Symbol::SimpleIdentifier => {
+                    atoms.push(child)
+                }
+                _ if child.child_count() == 0 => atoms.push(child),
+                _ => self.declaration_atoms(child, atoms),
+            }
+        }
+    }
+
+    /// Returns the source text of `nodes` with the whitespace between tokens replaced by a single
+    /// space, or `Doc::Nil` if there are no nodes
+    ///
+    /// Tokens themselves, e.g. string literals, are left as they are.
+    fn collapsed_text(&self, nodes: &[Node<'a>]) -> Doc<'a> {
+        let mut leaves = Vec::new();
+        for &node in nodes {
+            self.leaves(node, &mut leaves);
+        }
+
+        let start = match leaves.first() {
+            Some(first) => first.start_byte(),
+            None => return Doc::Nil,
+        };
+        let mut text = String::new();
+        let mut end = start;
+
+        for leaf in leaves {
+            if leaf.start_byte() > end {
+                text.push(' ');
+            }
+            text.push_str(self.text(leaf));
+            end = leaf.end_byte();
+        }
+
+        Doc::source(text, start)
+    }
+
+    fn leaves(&self, node: Node<'a>, leaves: &mut Vec<Node<'a>>) {
+        if node.child_count() == 0 {
+            leaves.push(node);
+        } else {
+            for child in node.children() {
+                self.leaves(child, leaves);
+            }
+        }
+    }
+
+    /// Returns the source text of `node` on a single line
+    ///
+    /// Used for expressions and other constructs within a line that no rule formats yet.  Comments
+    /// inside may need line breaks, so the text is kept as it is if there are any.
+    fn format_inline(&self, node: Node<'a>) -> Doc<'a> {
+        if self.comments.remaining(node).is_empty() {
+            self.collapsed_text(&[node])
+        } else {
+            self.format_verbatim(node)
+        }
+    }
+
+    /// Formats an item of a comma separated list with its rule or as its source text on one line
+    fn format_list_item(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        if self.has_rule(node) {
+            return self.format_node(node);
+        }
+
+        Ok(Doc::concat(vec![
+            self.format_leading_comments(node),
+            self.format_inline(node),
+            self.format_trailing_comments(node),
+        ]))
+    }
+
+    /// Returns the items of a `list_of_...` node, or `node` itself if it is not a list
+    fn list_items(&self, node: Node<'a>) -> Vec<Node<'a>> {
+        if node.kind().starts_with("list_of_") {
+            node.children()
+                .filter(|&child| child.is_named() && !is_comment(child))
+                .collect()
+        } else {
+            vec![node]
+        }
+    }
+
+    fn format_module_instantiation(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let mut docs = Vec::new();
+
+        for child in node.children() {
+            debug!("format_module_instantiation() child:{}", child.kind());
+            if is_comment(child) {
+                continue;
+            }
+
+            match child.kind() {
+                "," | ";" => docs.push(self.token(child)),
+                _ => {
+                    if !docs.is_empty() {
+                        docs.push(Doc::text(" "));
+                    }
+                    if child.is_named() {
+                        docs.push(self.format_item(child)?);
+                    } else {
+                        docs.push(self.token(child));
+                    }
+                }
+            }
+        }
+
+        Ok(Doc::concat(docs))
+    }
+
+    /// Formats the `#(...)` parameter overrides of an instance
+    fn format_parameter_value_assignment(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let assignments = node
+            .children()
+            .filter(|&child| child.is_named() && !is_comment(child))
+            .flat_map(|child| self.list_items(child))
+            .map(|assignment| self.format_list_item(assignment))
+            .collect::<Result<Vec<_>>>()?;
+
+        if assignments.is_empty() {
+            Ok(Doc::text("#()"))
+        } else {
+            Ok(Doc::concat(vec![
+                Doc::text("#"),
+                self.format_parenthesized_list(assignments, ")"),
+            ]))
+        }
+    }
+
+    /// Formats an instance name and its port connections
+    ///
+    /// Named connections go on lines of their own.  Ordered connections stay on the current line
+    /// if they fit.
+    fn format_hierarchical_instance(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let mut docs = Vec::new();
+        let mut connections = Vec::new();
+        let mut open_seen = false;
+
+        for child in node.children() {
+            debug!("format_hierarchical_instance() child:{}", child.kind());
+            if is_comment(child) {
+                continue;
+            }
+
+            match child.kind() {
+                "(" => open_seen = true,
+                ")" => {}
+                _ if open_seen => connections.extend(self.list_items(child)),
+                _ if child.is_named() => docs.push(self.format_item(child)?),
+                _ => docs.push(self.token(child)),
+            }
+        }
+
+        docs.push(Doc::text(" "));
+
+        let named = connections
+            .iter()
+            .any(|&connection| self.kinds.symbol(connection) == Symbol::NamedPortConnection);
+
+        if connections.is_empty() {
+            docs.push(Doc::text("()"));
+        } else if named {
+            let connections = if self.config.align_port_connections {
+                self.format_aligned_items(
+                    &connections,
+                    "",
+                    self.config.max_alignment_padding,
+                    |connection| Ok(self.connection_cells(connection)),
+                )?
+            } else {
+                connections
+                    .into_iter()
+                    .map(|connection| self.format_list_item(connection))
+                    .collect::<Result<Vec<_>>>()?
+            };
+
+            docs.push(Doc::text("("));
+            docs.push(Doc::indent(Doc::concat(vec![
+                Doc::HardLine,
+                Doc::join(
+                    connections,
+                    Doc::concat(vec![Doc::text(","), Doc::HardLine]),
+                ),
+            ])));
+            docs.push(Doc::HardLine);
+            docs.push(Doc::text(")"));
+        } else {
+            let connections = connections
+                .into_iter()
+                .map(|connection| self.format_list_item(connection))
+                .collect::<Result<Vec<_>>>()?;
+
+            docs.push(self.format_parenthesized_list(connections, ")"));
+        }
+
+        Ok(Doc::concat(docs))
+    }
+
+    /// Formats an `always`, `initial` or `final` block
+    fn format_procedural_block(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let mut docs = Vec::new();
+
+        for child in node.children() {
+            debug!("format_procedural_block() child:{}", child.kind());
+            if is_comment(child) {
+                continue;
+            }
+
+            if docs.is_empty() {
+                // The keyword
+                docs.push(self.format_inline(child));
+            } else {
+                docs.push(self.format_body(child)?);
+            }
+        }
+
+        Ok(Doc::concat(docs))
+    }
+
+    /// Formats the statement that a header such as `always` applies to
+    ///
+    /// Timing controls stay on the line of the header.  Blocks go where `Config::begin_style` puts
+    /// them.  Other statements are indented on the next line.
+    fn format_body(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let statement = self.unwrap_item(node);
+
+        match self.kinds.symbol(statement) {
+            Symbol::ProceduralTimingControlStatement => {
+                Ok(Doc::concat(vec![Doc::text(" "), self.format_item(node)?]))
+            }
+            Symbol::SeqBlock => {
+                let separator = match self.config.begin_style {
+                    BeginStyle::SameLine => Doc::text(" "),
+                    BeginStyle::OwnLine => Doc::HardLine,
+                };
+                Ok(Doc::concat(vec![separator, self.format_item(node)?]))
+            }
+            // A null statement
+            _ if self.text(node) == ";" => Ok(Doc::text(";")),
+            _ => Ok(Doc::indent(Doc::concat(vec![
+                Doc::HardLine,
+                self.format_item(node)?,
+            ]))),
+        }
+    }
+
+    /// Formats a `begin`/`end` block with its labels
+    fn format_seq_block(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let mut docs = Vec::new();
+        let mut label_follows = false;
+
+        for child in node.children() {
+            debug!("format_seq_block() child:{}", child.kind());
+            if is_comment(child) {
+                continue;
+            }
+
+            match child.kind() {
+                "begin" => docs.push(self.token(child)),
+                "end" => {
+                    docs.push(Doc::LineStart);
+                    docs.push(self.token(child));
+                }
+                ":" => label_follows = true,
+                _ if label_follows => {
+                    docs.push(Doc::text(" : "));
+                    docs.push(self.format_inline(child));
+                    label_follows = false;
+                }
+                _ => {
+                    let mut item = vec![Doc::LineStart];
+                    let items = &[Symbol::StatementOrNull, Symbol::BlockItemDeclaration];
+                    if self.blank_lines_after_previous_item(child, items) > 0 {
+                        item.push(Doc::MaybeBlankLine);
+                    }
+                    item.push(self.format_item(child)?);
+                    docs.push(Doc::indent(Doc::concat(item)));
+                }
+            }
+        }
+
+        Ok(Doc::concat(docs))
+    }
+
+    /// Formats an `if` statement with its `else if` and `else` branches
+    ///
+    /// An `else if` continues the chain at the same indentation instead of nesting.
+    fn format_conditional_statement(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let mut docs = Vec::new();
+        self.format_conditional_branches(node, &mut docs)?;
+        Ok(Doc::concat(docs))
+    }
+
+    fn format_conditional_branches(&self, node: Node<'a>, docs: &mut Vec<Doc<'a>>) -> Result<()> {
+        let mut if_seen = false;
+        let mut in_condition = false;
+        let mut else_seen = false;
+        let mut body_is_block = false;
+
+        for child in node.children() {
+            debug!("format_conditional_branches() child:{}", child.kind());
+            if is_comment(child) {
+                continue;
+            }
+
+            match child.kind() {
+                "(" => {
+                    docs.push(Doc::text(" "));
+                    docs.push(self.token(child));
+                    in_condition = true;
+                }
+                ")" => {
+                    docs.push(self.token(child));
+                    in_condition = false;
+                }
+                "else" => {
+                    if body_is_block && self.config.else_after_end && !follows_comment(child) {
+                        docs.push(Doc::text(" "));
+                    } else {
+                        docs.push(self.format_comments_before(child));
+                        docs.push(Doc::LineStart);
+                    }
+                    docs.push(self.token(child));
+                    else_seen = true;
+                }
+                _ if in_condition => {
+                    docs.push(self.format_leading_comments(child));
+                    docs.push(self.format_inline(child));
+                    docs.push(self.format_trailing_comments(child));
+                }
+                "if" => {
+                    if else_seen {
+                        docs.push(Doc::text(" "));
+                    }
+                    docs.push(self.token(child));
+                    if_seen = true;
+                    else_seen = false;
+                }
+                // `unique`, `priority` etc.
+                _ if !if_seen => {
+                    docs.push(self.format_inline(child));
+                    docs.push(Doc::text(" "));
+                }
+                _ => {
+                    let statement = self.unwrap_item(child);
+                    let symbol = self.kinds.symbol(statement);
+
+                    if else_seen && symbol == Symbol::ConditionalStatement {
+                        // `else if`
+                        docs.push(self.format_leading_comments(child));
+                        docs.push(Doc::text(" "));
+                        self.format_conditional_branches(statement, docs)?;
+                        docs.push(self.format_trailing_comments(child));
+                    } else {
+                        docs.push(self.format_body(child)?);
+                    }
+
+                    body_is_block = symbol == Symbol::SeqBlock;
+                    else_seen = false;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Formats a `case`, `casez` or `casex` statement
+    fn format_case_statement(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let mut docs = Vec::new();
+        let mut items = Vec::new();
+        let mut open_seen = false;
+        let mut close_seen = false;
+
+        for child in node.children() {
+            debug!("format_case_statement() child:{}", child.kind());
+            if is_comment(child) {
+                continue;
+            }
+
+            match child.kind() {
+                "(" => {
+                    docs.push(Doc::text(" "));
+                    docs.push(self.token(child));
+                    open_seen = true;
+                }
+                ")" => {
+                    docs.push(self.token(child));
+                    close_seen = true;
+                }
+                "inside" | "matches" => {
+                    docs.push(Doc::text(" "));
+                    docs.push(self.token(child));
+                }
+                "endcase" => {
+                    docs.push(self.format_case_items(&items)?);
+                    docs.push(Doc::LineStart);
+                    docs.push(self.token(child));
+                }
+                _ if close_seen => items.push(child),
+                // The case expression
+                _ if open_seen => docs.push(self.format_inline(child)),
+                // `unique`, `priority` etc. and the case keyword
+                _ => {
+                    if !docs.is_empty() {
+                        docs.push(Doc::text(" "));
+                    }
+                    docs.push(self.format_inline(child));
+                }
+            }
+        }
+
+        Ok(Doc::concat(docs))
+    }
+
+    fn format_case_items(&self, items: &[Node<'a>]) -> Result<Doc<'a>> {
+        let formatted = if self.config.align_case_items {
+            self.format_aligned_items(items, "", self.config.max_alignment_padding, |item| {
+                self.case_item_cells(item)
+            })?
+        } else {
+            items
+                .iter()
+                .map(|&item| self.format_item(item))
+                .collect::<Result<Vec<_>>>()?
+        };
+
+        let mut docs = Vec::new();
+        for (&item, doc) in items.iter().zip(formatted) {
+            let mut item_docs = vec![Doc::LineStart];
+            let kinds = &[Symbol::CaseItem, Symbol::CaseInsideItem];
+            if self.blank_lines_after_previous_item(item, kinds) > 0 {
+                item_docs.push(Doc::MaybeBlankLine);
+            }
+            item_docs.push(doc);
+            docs.push(Doc::indent(Doc::concat(item_docs)));
+        }
+
+        Ok(Doc::concat(docs))
+    }
+
+    /// Formats the values or `default` of a case item followed by its statement
+    ///
+    /// Simple statements stay on the line of the values.  Blocks go where `Config::begin_style`
+    /// puts them and other statements are indented on the next line.
+    fn format_case_item(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let (values, statement) = self.case_item_parts(node);
+        let mut docs = vec![values, Doc::text(":")];
+
+        if let Some(statement) = statement {
+            let inner = self.unwrap_item(statement);
+            if self.has_rule(inner) {
+                docs.push(self.format_body(statement)?);
+            } else {
+                docs.push(Doc::text(" "));
+                docs.push(self.format_item(statement)?);
+            }
+        }
+
+        Ok(Doc::concat(docs))
+    }
+
+    /// Splits a case item into cells for the values and for the `:` and the statement
+    ///
+    /// Returns `None` for items whose statement spans lines or has comments inside.
+    fn case_item_cells(&self, node: Node<'a>) -> Result<Option<Vec<Doc<'a>>>> {
+        if !self.comments.remaining(node).is_empty() {
+            return Ok(None);
+        }
+
+        let (values, statement) = self.case_item_parts(node);
+        let statement = match statement {
+            Some(statement) => statement,
+            None => return Ok(None),
+        };
+        if self.has_rule(self.unwrap_item(statement)) || self.text(statement).contains('\n') {
+            return Ok(None);
+        }
+
+        Ok(Some(vec![
+            values,
+            Doc::concat(vec![Doc::text(": "), self.format_item(statement)?]),
+        ]))
+    }
+
+    /// Returns the values or `default` of a case item and its statement
+    fn case_item_parts(&self, node: Node<'a>) -> (Doc<'a>, Option<Node<'a>>) {
+        let mut values = Vec::new();
+        let mut statement = None;
+        let mut colon_seen = false;
+
+        for child in node.children() {
+            if is_comment(child) {
+                continue;
+            }
+
+            match child.kind() {
+                ":" => colon_seen = true,
+                "," => values.push(Doc::text(", ")),
+                // The `:` after `default` is optional
+                "default" => {
+                    values.push(self.token(child));
+                    colon_seen = true;
+                }
+                _ if colon_seen => statement = Some(child),
+                _ => values.push(self.format_inline(child)),
+            }
+        }
+
+        (Doc::concat(values), statement)
+    }
+
+    /// Formats a `for`, `foreach`, `while`, `do ... while`, `repeat` or `forever` loop
+    fn format_loop_statement(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let mut docs = Vec::new();
+        let mut in_header = false;
+        let mut space_pending = false;
+        let mut body_seen = false;
+        let mut body_is_block = false;
+
+        for child in node.children() {
+            debug!("format_loop_statement() child:{}", child.kind());
+            if is_comment(child) {
+                continue;
+            }
+
+            match child.kind() {
+                "(" => {
+                    docs.push(Doc::text(" "));
+                    docs.push(self.token(child));
+                    in_header = true;
+                }
+                ")" => {
+                    docs.push(self.token(child));
+                    in_header = false;
+                    space_pending = false;
+                }
+                ";" if in_header => {
+                    docs.push(self.token(child));
+                    space_pending = true;
+                }
+                _ if in_header => {
+                    if space_pending {
+                        docs.push(Doc::text(" "));
+                        space_pending = false;
+                    }
+                    self.format_loop_header_part(child, &mut docs);
+                }
+                // The `while` of `do ... while`
+                "while" if body_seen => {
+                    if body_is_block && !follows_comment(child) {
+                        docs.push(Doc::text(" "));
+                    } else {
+                        docs.push(self.format_comments_before(child));
+                        docs.push(Doc::LineStart);
+                    }
+                    docs.push(self.token(child));
+                }
+                _ if child.is_named() => {
+                    docs.push(self.format_body(child)?);
+                    body_seen = true;
+                    body_is_block = self.kinds.symbol(self.unwrap_item(child)) == Symbol::SeqBlock;
+                }
+                // Keywords and the `;` after `do ... while`
+                _ => docs.push(self.token(child)),
+            }
+        }
+
+        Ok(Doc::concat(docs))
+    }
+
+    /// Adds a part of a loop header to `docs`, with a space after each `,` of a list such as the
+    /// index variables of `foreach`
+    fn format_loop_header_part(&self, node: Node<'a>, docs: &mut Vec<Doc<'a>>) {
+        if node.kind() == "," {
+            docs.push(self.token(node));
+            docs.push(Doc::text(" "));
+        } else if !node.is_named() {
+            docs.push(self.token(node));
+        } else if node.children().any(|child| child.kind() == ",")
+            && self.comments.remaining(node).is_empty()
+        {
+            for child in node.children() {
+                self.format_loop_header_part(child, docs);
+            }
+        } else {
+            docs.push(self.format_leading_comments(node));
+            docs.push(self.format_inline(node));
+            docs.push(self.format_trailing_comments(node));
+        }
+    }
+
+    /// Formats a statement preceded by an event or delay control
+    fn format_procedural_timing_control_statement(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let mut docs = Vec::new();
+
+        for child in node.children() {
+            if is_comment(child) {
+                continue;
+            }
+
+            if docs.is_empty() {
+                // The event or delay control
+                docs.push(self.format_item(child)?);
+            } else {
+                docs.push(self.format_body(child)?);
+            }
+        }
+
+        Ok(Doc::concat(docs))
+    }
+
+    /// Formats an event control, e.g. `@(posedge clock or negedge reset)` or `@*`
+    fn format_event_control(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        if !self.comments.remaining(node).is_empty() {
+            return Ok(self.format_verbatim(node));
+        }
+
+        let mut docs = Vec::new();
+        for child in node.children() {
+            if self.kinds.symbol(child) == Symbol::EventExpression {
+                self.format_event_expression(child, &mut docs);
+            } else if child.is_named() {
+                docs.push(self.format_inline(child));
+            } else {
+                docs.push(self.token(child));
+            }
+        }
+
+        Ok(Doc::concat(docs))
+    }
+
+    /// Adds the terms of a sensitivity list to `docs`, separated by single spaces
+    fn format_event_expression(&self, node: Node<'a>, docs: &mut Vec<Doc<'a>>) {
+        for child in node.children() {
+            match child.kind() {
+                "or" | "iff" => {
+                    docs.push(Doc::text(" "));
+                    docs.push(self.token(child));
+                    docs.push(Doc::text(" "));
+                }
+                "," => {
+                    docs.push(self.token(child));
+                    docs.push(Doc::text(" "));
+                }
+                _ if self.kinds.symbol(child) == Symbol::EventExpression => {
+                    self.format_event_expression(child, docs)
+                }
+                _ if matches!(self.text(child), "posedge" | "negedge" | "edge") => {
+                    docs.push(self.token(child));
+                    docs.push(Doc::text(" "));
+                }
+                _ if child.is_named() => docs.push(self.format_inline(child)),
+                _ => docs.push(self.token(child)),
+            }
+        }
+    }
+
+    fn format_continuous_assign(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        if !self.comments.remaining(node).is_empty() {
+            return Ok(self.format_verbatim(node));
+        }
+
+        let (mut docs, assignments) = self.continuous_assign_parts(node);
+        let assignments = assignments.into_iter().map(|assignment| {
+            let (left, right) = self.net_assignment_sides(assignment);
+            Doc::concat(vec![left, Doc::text(" = "), right])
+        });
+
+        docs.push(Doc::text(" "));
+        docs.push(Doc::join(assignments, Doc::text(", ")));
+        docs.push(Doc::text(";"));
+        Ok(Doc::concat(docs))
+    }
+
+    /// Splits a continuous assignment into cells for everything before the `=` and for the `=` and
+    /// the expression, without the `;`
+    ///
+    /// Returns `None` for other items, assignments of several nets and assignments with comments
+    /// inside.
+    fn continuous_assign_cells(&self, node: Node<'a>) -> Option<Vec<Doc<'a>>> {
+        if self.kinds.symbol(node) != Symbol::ContinuousAssign
+            || !self.comments.remaining(node).is_empty()
+        {
+            return None;
+        }
+
+        let (mut docs, assignments) = self.continuous_assign_parts(node);
+        if assignments.len() != 1 {
+            return None;
+        }
+        let (left, right) = self.net_assignment_sides(assignments[0]);
+
+        docs.push(Doc::text(" "));
+        docs.push(left);
+        Some(vec![
+            Doc::concat(docs),
+            Doc::concat(vec![Doc::text("= "), right]),
+        ])
+    }
+
+    /// Returns the `assign` keyword with any drive strength and delay, and the net assignments
+    fn continuous_assign_parts(&self, node: Node<'a>) -> (Vec<Doc<'a>>, Vec<Node<'a>>) {
+        let mut docs = Vec::new();
+        let mut assignments = Vec::new();
+
+        for child in node.children() {
+            if is_comment(child) || child.kind() == ";" {
+                continue;
+            }
+
+            if child.kind() == "assign" {
+                docs.push(self.token(child));
+            } else if child.kind().starts_with("list_of_") {
+                assignments = self.list_items(child);
+            } else {
+                // Drive strength or delay
+                docs.push(Doc::text(" "));
+                docs.push(self.format_inline(child));
+            }
+        }
+
+        (docs, assignments)
+    }
+
+    /// Returns the net and the expression of an assignment
+    fn net_assignment_sides(&self, node: Node<'a>) -> (Doc<'a>, Doc<'a>) {
+        let parts = node.children().collect::<Vec<_>>();
+
+        match parts.iter().position(|part| part.kind() == "=") {
+            Some(equals) => (
+                self.collapsed_text(&parts[..equals]),
+                self.collapsed_text(&parts[equals + 1..]),
+            ),
+            None => (self.format_inline(node), Doc::Nil),
+        }
+    }
+
+    /// Formats a named port connection or parameter assignment, e.g. `.name(expression)`, `.name`
+    /// or `.*`
+    fn format_named_connection(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let parts = node
+            .children()
+            .filter(|&child| !is_comment(child))
+            .collect::<Vec<_>>();
+
+        Ok(self.format_connection_parts(&parts))
+    }
+
+    /// Splits a named port connection into cells for the `.name` and the parenthesized expression
+    ///
+    /// Returns `None` for other connections and connections with comments inside.
+    fn connection_cells(&self, node: Node<'a>) -> Option<Vec<Doc<'a>>> {
+        if self.kinds.symbol(node) != Symbol::NamedPortConnection
+            || !self.comments.remaining(node).is_empty()
+        {
+            return None;
+        }
+
+        let parts = node.children().collect::<Vec<_>>();
+        let open = parts
+            .iter()
+            .position(|part| part.kind() == "(")
+            .unwrap_or(parts.len());
+
+        Some(vec![
+            self.format_connection_parts(&parts[..open]),
+            self.format_connection_parts(&parts[open..]),
+        ])
+    }
+
+    fn format_connection_parts(&self, parts: &[Node<'a>]) -> Doc<'a> {
+        if parts.is_empty() {
+            return Doc::Nil;
+        }
+
+        Doc::concat(
+            parts
+                .iter()
+                .map(|&part| {
+                    if part.is_named() {
+                        self.format_inline(part)
+                    } else {
+                        self.token(part)
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// Formats items, lining up the cells of consecutive items in columns
+    ///
+    /// `cells` splits an item into cells or returns `None` if the item doesn't fit the columns.
+    /// Such items are formatted with `format_item()`.  They end a run of aligned items, as do a
+    /// blank line, a comment on the line before an item and an item with a different number of
+    /// cells.  A run of a single item isn't aligned and is formatted with `format_item()` if a rule
+    /// formats it.  `suffix` follows each aligned item, e.g. the `;` that isn't part of any cell.
+    /// See `align_columns()` for `max_padding`.
+    fn format_aligned_items<F>(
+        &self,
+        items: &[Node<'a>],
+        suffix: &'a str,
+        max_padding: usize,
+        cells: F,
+    ) -> Result<Vec<Doc<'a>>>
+    where
+        F: Fn(Node<'a>) -> Result<Option<Vec<Doc<'a>>>>,
+    {
+        let mut docs = Vec::new();
+        let mut run: Vec<(Node<'a>, Vec<Doc<'a>>)> = Vec::new();
+
+        for &item in items {
+            let item_cells = cells(item)?;
+            let continues_run = match (&item_cells, run.last()) {
+                (Some(item_cells), Some((_, previous_cells))) => {
+                    item_cells.len() == previous_cells.len()
+                        && self.blank_lines_before(item) == 0
+                        && self.comments.leading(item).is_empty()
+                }
+                _ => false,
+            };
+            if !continues_run {
+                docs.extend(self.align_run(mem::take(&mut run), suffix, max_padding)?);
+            }
+
+            match item_cells {
+                Some(item_cells) => run.push((item, item_cells)),
+                None => docs.push(self.format_item(item)?),
+            }
+        }
+        docs.extend(self.align_run(run, suffix, max_padding)?);
+
+        Ok(docs)
+    }
+
+    fn align_run(
+        &self,
+        run: Vec<(Node<'a>, Vec<Doc<'a>>)>,
+        suffix: &'a str,
+        max_padding: usize,
+    ) -> Result<Vec<Doc<'a>>> {
+        // There is nothing to line up with.  Items without a rule keep their cells, which give
+        // them their spacing.
+        if let [(item, _)] = run[..] {
+            if self.has_rule(self.unwrap_item(item)) {
+                return Ok(vec![self.format_item(item)?]);
+            }
+        }
+
+        let (items, rows): (Vec<_>, Vec<_>) = run.into_iter().unzip();
+        let rows = align_columns(rows, max_padding);
+
+        Ok(items
+            .into_iter()
+            .zip(rows)
+            .map(|(item, row)| {
+                Doc::concat(vec![
+                    self.format_leading_comments(item),
+                    row,
+                    Doc::text(suffix),
+                    self.format_trailing_comments(item),
+                    self.format_dangling_comments(item),
+                ])
+            })
+            .collect())
+    }
+
+    fn format_tf_port_list(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        let ports = node
+            .children()
+            .filter(|&child| child.is_named() && !is_comment(child))
+            .map(|child| self.format_node(child))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(self.format_parenthesized_list(ports, ");"))
+    }
+
+    /// Formats a function statement followed by the `;` that ends it
+    ///
+    /// The statement's own `;` has no rule and is dropped by `format_children()`.
+    fn format_function_statement_or_null(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        Ok(Doc::concat(vec![
+            self.format_children(node)?,
+            Doc::text(";"),
+            Doc::HardLine,
+        ]))
+    }
+
+    /// Returns the number of blank lines between `node` and the previous function item
+    ///
+    /// Leading comments are considered part of `node`.
+    fn blank_lines_after_previous_function_item(&self, node: Node<'a>) -> usize {
+        // Need to check the previous symbol type because the grammar mixes function items with
+        // function head items.
+        self.blank_lines_after_previous_item(node, &[Symbol::FunctionStatementOrNull])
+    }
+
+    /// Returns the number of blank lines between `node` and the previous item of its block
+    ///
+    /// Leading comments are considered part of `node`.  A previous sibling that is neither a
+    /// comment nor of one of the `items` kinds belongs to the head of the block, e.g. a label, so
+    /// there is no previous item.
+    fn blank_lines_after_previous_item(&self, node: Node<'a>, items: &[Symbol]) -> usize {
+        let node = self.comments.leading(node).first().cloned().unwrap_or(node);
+
+        match node.prev_sibling() {
+            Some(prev) if is_comment(prev) || items.contains(&self.kinds.symbol(prev)) => {
+                self.blank_lines_between(prev, node)
+            }
+            _ => 0,
+        }
+    }
+
+    /// Returns the number of blank lines between `node` and its previous sibling
+    ///
+    /// Leading comments are considered part of `node`.
+    fn blank_lines_before(&self, node: Node<'a>) -> usize {
+        let node = self.comments.leading(node).first().cloned().unwrap_or(node);
+
+        node.prev_sibling()
+            .map_or(0, |prev| self.blank_lines_between(prev, node))
+    }
+
+    fn blank_lines_between(&self, prev: Node<'a>, node: Node<'a>) -> usize {
+        let difference = node.start_position().row - prev.end_position().row;
+
+        if difference == 0 {
+            0
+        } else {
+            difference - 1
+        }
+    }
+}
+
+/// Iterator struct for iterating over all terminal nodes
+struct Terminals<'a> {
+    index: usize,
+    terminals: Vec<Node<'a>>,
+}
+
+impl<'a> Terminals<'a> {
+    fn new(node: Node<'a>) -> Self {
+        let mut terminals = Vec::new();
+
+        Self::collect_terminals(node, &mut terminals);
+
+        Terminals {
+            index: 0,
+            terminals,
+        }
+    }
+
+    fn collect_terminals(node: Node<'a>, terminals: &mut Vec<Node<'a>>) {
+        for child in node.children() {
+            // Comments are emitted separately.  See format_dangling_comments().
+            if is_comment(child) {
+                continue;
+            }
             if child.child_count() == 0 {
                 terminals.push(child);
             }
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use tree_sitter::Node;
+
+use crate::{Config, Doc, Formatter, Result};
+
+/// A formatting rule for one kind of syntax node
+///
+/// Rules receive the node to format and a context that gives access to the source text and to
+/// the formatter for recursing into children.  Rules return a document that the formatter lays
+/// out along with the rest of the file.
+pub type Rule = dyn for<'f, 'a> Fn(Node<'a>, &Context<'f, 'a>) -> Result<Doc<'a>> + Send + Sync;
+
+/// A registry of formatting rules by node kind
+///
+/// Rules registered here take precedence over the built-in rules.  This allows the layout of a
+/// construct to be changed without modifying svfmt.
+///
+/// ```ignore
+/// let mut config = Config::default();
+/// config.rules.register("primary_literal", |node, cx| {
+///     Ok(Doc::text(cx.text(node).to_uppercase()))
+/// });
+/// ```
+#[derive(Clone, Default)]
+pub struct Rules {
+    rules: HashMap<String, Arc<Rule>>,
+}
+
+impl Rules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a rule for a node kind, replacing any rule previously registered for the kind
+    ///
+    /// The kind is the name used by the grammar, e.g. `"hierarchical_instance"`.
+    pub fn register<F>(&mut self, kind: &str, rule: F)
+    where
+        F: for<'f, 'a> Fn(Node<'a>, &Context<'f, 'a>) -> Result<Doc<'a>> + Send + Sync + 'static,
+    {
+        self.rules.insert(kind.to_string(), Arc::new(rule));
+    }
+
+    /// Removes the rule registered for a node kind, restoring the built-in rule
+    pub fn unregister(&mut self, kind: &str) {
+        self.rules.remove(kind);
+    }
+
+    pub(crate) fn get(&self, kind: &str) -> Option<&Rule> {
+        self.rules.get(kind).map(|rule| rule.as_ref())
+    }
+}
+
+impl fmt::Debug for Rules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut kinds = self.rules.keys().collect::<Vec<_>>();
+        kinds.sort();
+        f.debug_set().entries(kinds).finish()
+    }
+}
+
+/// Access to the formatter from within a rule
+pub struct Context<'f, 'a> {
+    formatter: &'f Formatter<'a>,
+}
+
+impl<'f, 'a> Context<'f, 'a> {
+    pub(crate) fn new(formatter: &'f Formatter<'a>) -> Self {
+        Self { formatter }
+    }
+
+    pub fn config(&self) -> &Config {
+        self.formatter.config
+    }
+
+    /// Returns the source text of a node
+    pub fn text(&self, node: Node<'a>) -> &'a str {
+        self.formatter.text(node)
+    }
+
+    /// Returns the source text of a node as a document that is tracked by the source map
+    pub fn token(&self, node: Node<'a>) -> Doc<'a> {
+        self.formatter.token(node)
+    }
+
+    /// Formats a node, including its comments, using the rule registered for its kind
+    pub fn format_child(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        self.formatter.format_node(node)
+    }
+
+    /// Formats each child of a node in turn
+    pub fn format_children(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        self.formatter.format_children(node)
+    }
+
+    /// Formats a node using the built-in rule for its kind
+    ///
+    /// This allows a rule to decorate the built-in layout instead of replacing it.
+    pub fn format_builtin(&self, node: Node<'a>) -> Result<Doc<'a>> {
+        self.formatter.format_builtin(node)
+    }
+}
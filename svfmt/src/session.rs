@@ -0,0 +1,192 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use snafu::{ensure, OptionExt, ResultExt};
+use tree_sitter::{Language, Parser, Tree};
+
+use crate::idempotency::verify_idempotent_with;
+use crate::kinds::Kinds;
+use crate::{
+    format_encoded, format_into, tree_sitter_verilog, Buffer, Config, Instability, ParseCancelled,
+    ReadError, Result, BYTE_ORDER_MARK,
+};
+
+/// A parser and formatting options that are set up once and reused
+///
+/// Creating a session configures a parser and looks up the node kinds of its language.  Services
+/// that format many sources should keep sessions around, e.g. one per worker thread or in a pool,
+/// instead of calling `parse()` and `format()` for each source.  A session is `Send` but formats
+/// one source at a time.
+///
+/// ```ignore
+/// let mut session = Session::new(Config::default())?;
+/// session.set_timeout(Some(Duration::from_secs(1)));
+/// let formatted = session.format("function int f(a);\nendfunction\n")?;
+/// ```
+pub struct Session {
+    // Declared before the flag so that the parser, which points to the flag, is dropped first
+    parser: Parser,
+    cancellation_flag: Arc<AtomicUsize>,
+    language: Language,
+    config: Config,
+    kinds: Kinds,
+}
+
+impl Session {
+    /// Creates a session for Verilog
+    ///
+    /// Fails with `Error::MissingRuleKinds` if the grammar lacks a node kind that a built-in rule
+    /// matches on.
+    pub fn new(config: Config) -> Result<Self> {
+        let session = Self::with_language(unsafe { tree_sitter_verilog() }, config)?;
+        session.kinds.check()?;
+        Ok(session)
+    }
+
+    /// Creates a session that parses sources with `language`
+    ///
+    /// The built-in rules are written for Verilog and match node kinds by name.  Rules for kinds
+    /// that `language` doesn't define are disabled.  `missing_rule_kinds()` lists those kinds.
+    pub fn with_language(language: Language, config: Config) -> Result<Self> {
+        let mut parser = Parser::new();
+        parser.set_language(language)?;
+        let kinds = Kinds::new(language);
+
+        let cancellation_flag = Arc::new(AtomicUsize::new(0));
+        // The flag lives as long as the parser.  The Arc keeps its address stable when the
+        // session is moved.
+        unsafe { parser.set_cancellation_flag(Some(&cancellation_flag)) };
+
+        Ok(Self {
+            parser,
+            cancellation_flag,
+            language,
+            config,
+            kinds,
+        })
+    }
+
+    /// Returns the language sources are parsed with
+    pub fn language(&self) -> Language {
+        self.language
+    }
+
+    /// Returns the node kinds that built-in rules match on but the session's language doesn't
+    /// define
+    pub fn missing_rule_kinds(&self) -> &[&'static str] {
+        self.kinds.missing()
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Limits how long parsing a source may take, or removes the limit if `timeout` is `None`
+    ///
+    /// Formatting fails with `Error::ParseCancelled` if the limit is reached.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        let micros = timeout.map_or(0, |timeout| timeout.as_micros().max(1) as u64);
+        self.parser.set_timeout_micros(micros);
+    }
+
+    /// Returns a flag that cancels parsing when set to a non-zero value
+    ///
+    /// The flag may be set from another thread.  Formatting then fails with
+    /// `Error::ParseCancelled`.  The flag stays set until it is stored back to zero.
+    pub fn cancellation_flag(&self) -> Arc<AtomicUsize> {
+        self.cancellation_flag.clone()
+    }
+
+    /// Formats source in the session's language
+    pub fn format(&mut self, source: &str) -> Result<String> {
+        let mut output = Vec::new();
+        self.format_to(&mut output, source)?;
+
+        // The source is UTF-8 so the output is as well
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    /// Formats a file and returns the output in the encoding of the file
+    ///
+    /// The file itself is left unchanged.
+    pub fn format_file(&mut self, path: &Path) -> Result<Vec<u8>> {
+        let source = fs::read(path).context(ReadError { path })?;
+        self.format_bytes(&source).map_err(|e| e.with_path(path))
+    }
+
+    /// Formats source in any supported encoding and returns the output in the encoding of the
+    /// source
+    ///
+    /// The source is decoded and its byte-order mark handled as described for
+    /// `svfmt::format_bytes()`.
+    pub fn format_bytes(&mut self, source: &[u8]) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        self.format_bytes_to(&mut output, source)?;
+        Ok(output)
+    }
+
+    /// Formats source in the session's language and writes the output to `f`
+    ///
+    /// Lines are written as they are formatted instead of being collected first.  A leading
+    /// U+FEFF is handled like the byte-order mark of `format_bytes()`.
+    pub fn format_to<T>(&mut self, f: &mut T, source: &str) -> Result<()>
+    where
+        T: io::Write,
+    {
+        let source = match source.strip_prefix('\u{feff}') {
+            Some(source) => {
+                if self.config.keep_byte_order_mark {
+                    f.write_all(BYTE_ORDER_MARK)?;
+                }
+                source
+            }
+            None => source,
+        };
+
+        let tree = parse(&mut self.parser, &self.cancellation_flag, source)?;
+        format_into(Buffer::new(f), source, &tree, &self.config, &self.kinds)?;
+        Ok(())
+    }
+
+    /// Formats source in any supported encoding like `format_bytes()` and writes the output to
+    /// `f` as it is formatted
+    pub fn format_bytes_to<T>(&mut self, f: &mut T, source: &[u8]) -> Result<()>
+    where
+        T: io::Write,
+    {
+        let Session {
+            parser,
+            cancellation_flag,
+            config,
+            kinds,
+            ..
+        } = self;
+        format_encoded(f, source, config, |f, source| {
+            let tree = parse(parser, cancellation_flag, source)?;
+            format_into(Buffer::new(f), source, &tree, config, kinds)?;
+            Ok(())
+        })
+    }
+
+    /// Formats source and then formats the output again, like `svfmt::verify_idempotent()`
+    pub fn verify_idempotent(&mut self, source: &str) -> Result<Option<Instability>> {
+        verify_idempotent_with(source, |source| self.format(source))
+    }
+}
+
+fn parse(parser: &mut Parser, cancellation_flag: &AtomicUsize, source: &str) -> Result<Tree> {
+    // The parser only checks the flag every so many steps, so a short source would be parsed
+    // even though the flag is set
+    ensure!(
+        cancellation_flag.load(Ordering::SeqCst) == 0,
+        ParseCancelled
+    );
+
+    // Discard any state left by a cancelled parse
+    parser.reset();
+    parser.parse(source, None).context(ParseCancelled)
+}
@@ -0,0 +1,70 @@
+/// The position of a piece of source text in the output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Mapping {
+    /// The byte offset of the text in the source
+    pub(crate) source: usize,
+
+    /// The byte offset of the text in the output
+    pub(crate) output: usize,
+
+    /// The length of the text in bytes
+    pub(crate) length: usize,
+}
+
+/// Translates positions between a source file and its formatted output
+///
+/// Formatting moves tokens but does not change them, so a position within a token maps to the same
+/// position within the token in the other file.  A position between tokens (e.g. in whitespace that
+/// formatting replaced) maps to the end of the preceding token.
+///
+/// Positions are byte offsets into the UTF-8 text.  Editors use this to keep the cursor and marks
+/// in place after formatting.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    /// Mappings ordered by source offset
+    by_source: Vec<Mapping>,
+
+    /// Mappings ordered by output offset
+    by_output: Vec<Mapping>,
+}
+
+impl SourceMap {
+    pub(crate) fn new(mappings: Vec<Mapping>) -> Self {
+        let mut by_source = mappings.clone();
+        by_source.sort_by_key(|mapping| mapping.source);
+
+        let mut by_output = mappings;
+        by_output.sort_by_key(|mapping| mapping.output);
+
+        Self {
+            by_source,
+            by_output,
+        }
+    }
+
+    /// Translates a byte offset in the source to the corresponding byte offset in the output
+    pub fn to_output(&self, source_offset: usize) -> usize {
+        translate(&self.by_source, source_offset, |m| m.source, |m| m.output)
+    }
+
+    /// Translates a byte offset in the output to the corresponding byte offset in the source
+    pub fn to_source(&self, output_offset: usize) -> usize {
+        translate(&self.by_output, output_offset, |m| m.output, |m| m.source)
+    }
+}
+
+/// Finds the last mapping that starts at or before `offset` and moves `offset` to the other side
+fn translate<F, T>(mappings: &[Mapping], offset: usize, from: F, to: T) -> usize
+where
+    F: Fn(&Mapping) -> usize,
+    T: Fn(&Mapping) -> usize,
+{
+    let index = match mappings.binary_search_by_key(&offset, &from) {
+        Ok(index) => index,
+        Err(0) => return 0,
+        Err(index) => index - 1,
+    };
+    let mapping = &mappings[index];
+
+    to(mapping) + (offset - from(mapping)).min(mapping.length)
+}
@@ -0,0 +1,24 @@
+//! Typed wrappers for syntax nodes
+//!
+//! The wrappers are generated by build.rs from the grammar's node-types.json.  Each wrapper gives
+//! access to a node's fields and children by name instead of by position, so formatting rules keep
+//! working when the grammar adds, removes or reorders children.
+
+// A wrapper is generated for every node kind of the grammar but rules use only some of them
+#![allow(dead_code)]
+
+use tree_sitter::Node;
+
+/// A syntax node of a known kind
+pub(crate) trait Syntax<'a>: Sized {
+    /// The kind of node wrapped, as named by the grammar
+    const KIND: &'static str;
+
+    /// Wraps `node` if it is of this kind
+    fn cast(node: Node<'a>) -> Option<Self>;
+
+    /// Returns the wrapped node
+    fn node(&self) -> Node<'a>;
+}
+
+include!(concat!(env!("OUT_DIR"), "/syntax.rs"));
@@ -28,4 +28,26 @@ mod assignment {
 
         assert_eq!(&transform(input), expected);
     }
+
+    #[test]
+    fn operator_assignment_expression() {
+        init();
+
+        let input = indoc!(
+            "
+            function int f(a, b);
+                a=a+b;
+            endfunction
+            "
+        );
+        let expected = indoc!(
+            "
+            function int f(a, b);
+                a = a + b;
+            endfunction
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
 }
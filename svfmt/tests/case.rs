@@ -0,0 +1,112 @@
+#[macro_use]
+mod common;
+
+mod case {
+    use crate::common::*;
+    use indoc::indoc;
+    use svfmt::Config;
+
+    #[test]
+    fn items() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            always_comb
+            unique case(state)
+            IDLE:next = RUN;
+            RUN,WAIT : begin
+            next = DONE;
+            end
+            default next = IDLE;
+            endcase
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                always_comb
+                    unique case (state)
+                        IDLE: next = RUN;
+                        RUN, WAIT: begin
+                            next = DONE;
+                        end
+                        default: next = IDLE;
+                    endcase
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn inside() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            always_comb priority case (opcode) inside
+            [0:3]: kind = ALU;
+            4'b1???: kind = BRANCH;
+            endcase
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                always_comb
+                    priority case (opcode) inside
+                        [0:3]: kind = ALU;
+                        4'b1???: kind = BRANCH;
+                    endcase
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn align() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            always_comb
+            case (state)
+            IDLE: next = RUN;
+            RUN, WAIT: next = DONE;
+            DONE: begin
+            next = IDLE;
+            end
+            default: next = IDLE;
+            endcase
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                always_comb
+                    case (state)
+                        IDLE      : next = RUN;
+                        RUN, WAIT : next = DONE;
+                        DONE: begin
+                            next = IDLE;
+                        end
+                        default: next = IDLE;
+                    endcase
+            endmodule
+            "
+        );
+        let config = Config::default().align_case_items(true);
+
+        assert_eq!(&transform_with_config(input, &config), expected);
+    }
+}
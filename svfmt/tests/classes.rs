@@ -34,4 +34,52 @@ mod classes {
 
         assert_eq!(&transform(input), expected);
     }
+
+    #[test]
+    fn parameters() {
+        init();
+
+        let input = indoc!(
+            "
+            class stack #(type T=int, int DEPTH =8);
+            function int f(int a);
+            endfunction
+            endclass
+            "
+        );
+        let expected = indoc!(
+            "
+            class stack #(type T = int, int DEPTH = 8);
+                function int f(int a);
+                endfunction
+            endclass
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn empty() {
+        init();
+
+        let input = indoc!(
+            "
+            class a;endclass
+            class b #(int N=1);
+            endclass
+            "
+        );
+        let expected = indoc!(
+            "
+            class a;
+            endclass
+
+            class b #(int N = 1);
+            endclass
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
 }
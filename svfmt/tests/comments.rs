@@ -0,0 +1,133 @@
+#[macro_use]
+mod common;
+
+mod comments {
+    use crate::common::*;
+    use indoc::indoc;
+    use svfmt::Config;
+
+    #[test]
+    fn file_scope() {
+        init();
+
+        let input = indoc!(
+            "
+            // File header
+
+            // Function f
+            function int f(int a);
+                return a;
+            endfunction
+            "
+        );
+        let expected = indoc!(
+            "
+            // File header
+
+            // Function f
+            function int f(int a);
+                return a;
+            endfunction
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn class_items() {
+        init();
+
+        let input = indoc!(
+            "
+            class myclass;
+            // Method f
+            function int f(int a);
+            return a;
+            endfunction
+            endclass
+            "
+        );
+        let expected = indoc!(
+            "
+            class myclass;
+                // Method f
+                function int f(int a);
+                    return a;
+                endfunction
+            endclass
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn arguments() {
+        init();
+
+        let input = indoc!(
+            "
+            function int f(int a, int b);
+                return g(a /* first */ , b);
+            endfunction
+            "
+        );
+        let expected = indoc!(
+            "
+            function int f(int a, int b);
+                return g(a /* first */, b);
+            endfunction
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn end_of_line() {
+        init();
+
+        let input = indoc!(
+            "
+            function int f(a);
+                a = 1; // reset value
+                a  =  2;   /* set */
+            endfunction
+            "
+        );
+        let expected = indoc!(
+            "
+            function int f(a);
+                a = 1; // reset value
+                a = 2; /* set */
+            endfunction
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn end_of_line_spacing() {
+        init();
+
+        let input = indoc!(
+            "
+            function int f(a); // f
+                a = 1; // reset value
+            endfunction
+            "
+        );
+        let expected = indoc!(
+            "
+            function int f(a);  // f
+                a = 1;  // reset value
+            endfunction
+            "
+        );
+        let config = Config::default().comment_spacing(2);
+
+        assert_eq!(&transform_with_config(input, &config), expected);
+    }
+}
@@ -1,12 +1,19 @@
+// Not every test uses every helper
+#![allow(dead_code)]
+
 use std::fmt;
 
 use env_logger;
 use svfmt::*;
 
 pub fn transform(source: &str) -> String {
+    transform_with_config(source, &Config::default())
+}
+
+pub fn transform_with_config(source: &str, config: &Config) -> String {
     let tree = parse(unsafe { tree_sitter_verilog() }, source).unwrap();
     let mut s = Vec::new();
-    format(&mut s, source, &tree).unwrap();
+    format_with_config(&mut s, source, &tree, config).unwrap();
     String::from_utf8_lossy(&s).to_string()
 }
 
@@ -0,0 +1,184 @@
+#[macro_use]
+mod common;
+
+mod conditionals {
+    use crate::common::*;
+    use indoc::indoc;
+    use svfmt::Config;
+
+    #[test]
+    fn else_if_chain() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            always_comb begin
+            if(a) begin
+            x = 1;
+            end
+            else if ( b ) begin
+            x = 2;
+            end
+            else begin
+            x = 3;
+            end
+            end
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                always_comb begin
+                    if (a) begin
+                        x = 1;
+                    end else if (b) begin
+                        x = 2;
+                    end else begin
+                        x = 3;
+                    end
+                end
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn single_statements() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            always_comb
+            unique if (a==b) x = 1; else x = 2;
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                always_comb
+                    unique if (a==b)
+                        x = 1;
+                    else
+                        x = 2;
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn else_on_own_line() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            always_comb if (a) begin
+            x = 1;
+            end else begin
+            x = 2;
+            end
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                always_comb
+                    if (a) begin
+                        x = 1;
+                    end
+                    else begin
+                        x = 2;
+                    end
+            endmodule
+            "
+        );
+        let config = Config::default().else_after_end(false);
+
+        assert_eq!(&transform_with_config(input, &config), expected);
+    }
+
+    #[test]
+    fn else_if_single_statements() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            always_comb
+            if (a) x = 1;
+            else if (b) x = 2;
+            else if (c) x = 3;
+            else x = 4;
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                always_comb
+                    if (a)
+                        x = 1;
+                    else if (b)
+                        x = 2;
+                    else if (c)
+                        x = 3;
+                    else
+                        x = 4;
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn comments() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            always_comb begin
+            if (a) // first
+            x = 1;
+            // second
+            else if (b) begin
+            x = 2;
+            end // third
+            else begin
+            x = 3;
+            end
+            end
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                always_comb begin
+                    if (a) // first
+                        x = 1;
+                    // second
+                    else if (b) begin
+                        x = 2;
+                    end // third
+                    else begin
+                        x = 3;
+                    end
+                end
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+}
@@ -0,0 +1,67 @@
+#[macro_use]
+mod common;
+
+mod continuous_assignments {
+    use crate::common::*;
+    use indoc::indoc;
+    use svfmt::Config;
+
+    #[test]
+    fn spacing() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            assign   (strong0, weak1)  #( 2 )   a=b&c;
+            assign x = 1'b0,y = 1'b1;
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                assign (strong0, weak1) #( 2 ) a = b&c;
+                assign x = 1'b0, y = 1'b1;
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn align() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            assign a = b;
+            assign long_name = c;
+
+            assign x = y;
+            // Not aligned with the assignment above
+            assign xyz = 1;
+            assign #1 z = 0;
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                assign a         = b;
+                assign long_name = c;
+
+                assign x = y;
+                // Not aligned with the assignment above
+                assign xyz  = 1;
+                assign #1 z = 0;
+            endmodule
+            "
+        );
+        let config = Config::default().align_continuous_assignments(true);
+
+        assert_eq!(&transform_with_config(input, &config), expected);
+    }
+}
@@ -0,0 +1,102 @@
+#[macro_use]
+mod common;
+
+mod diagnostics {
+    use crate::common::*;
+    use indoc::indoc;
+    use std::path::PathBuf;
+    use svfmt::{format, parse, tree_sitter_verilog, Error, Location};
+    use tree_sitter::Point;
+
+    #[test]
+    fn render() {
+        init();
+
+        let source = indoc!(
+            "
+            function int f(a);
+                a = 1;
+            endfunction
+            "
+        );
+        let location = Location {
+            path: Some(PathBuf::from("f.sv")),
+            start_byte: 23,
+            end_byte: 28,
+            start: Point { row: 1, column: 4 },
+            end: Point { row: 1, column: 9 },
+            kind: "operator_assignment",
+        };
+        let expected = indoc!(
+            "
+            error: Invalid node kind.
+             --> f.sv:2:5
+              |
+            2 |     a = 1;
+              |     ^^^^^ operator_assignment
+            "
+        );
+
+        assert_eq!(&location.render("Invalid node kind.", source), expected);
+    }
+
+    #[test]
+    fn render_multiline() {
+        init();
+
+        let source = indoc!(
+            "
+            function int f(a);
+                a = 1;
+            endfunction
+            "
+        );
+        let location = Location {
+            path: None,
+            start_byte: 0,
+            end_byte: 41,
+            start: Point { row: 0, column: 0 },
+            end: Point { row: 2, column: 11 },
+            kind: "function_declaration",
+        };
+        let expected = indoc!(
+            "
+            error: Invalid node child count.
+             --> 1:1
+              |
+            1 | function int f(a);
+              | ^^^^^^^^^^^^^^^^^^ function_declaration
+            "
+        );
+
+        assert_eq!(
+            &location.render("Invalid node child count.", source),
+            expected
+        );
+    }
+
+    #[test]
+    fn syntax_error() {
+        init();
+
+        // The closing parenthesis is missing
+        let source = indoc!(
+            "
+            function int f(a);
+                return (a;
+            endfunction
+            "
+        );
+        let tree = parse(unsafe { tree_sitter_verilog() }, source).unwrap();
+        let error = format(&mut Vec::new(), source, &tree).unwrap_err();
+
+        match error {
+            Error::SyntaxError { .. } => {}
+            _ => panic!("unexpected error {:?}", error),
+        }
+        assert!(error.location().unwrap().start.row == 1);
+        assert!(error
+            .render(source)
+            .starts_with("error: Syntax error.\n --> 2:"));
+    }
+}
@@ -0,0 +1,134 @@
+#[macro_use]
+mod common;
+
+mod edits {
+    use crate::common::*;
+    use indoc::indoc;
+    use svfmt::{text_edits, TextEdit};
+
+    /// Applies edits in reverse order so that earlier ranges stay valid
+    fn apply(original: &str, edits: &[TextEdit]) -> String {
+        let mut text = original.to_string();
+        for edit in edits.iter().rev() {
+            text.replace_range(edit.range.clone(), &edit.replacement);
+        }
+        text
+    }
+
+    #[test]
+    fn unchanged() {
+        init();
+
+        let text = "function int f(a);\nendfunction\n";
+
+        assert!(text_edits(text, text).is_empty());
+    }
+
+    #[test]
+    fn spacing() {
+        init();
+
+        let original = indoc!(
+            "
+            function int f(a);
+              a   = b;
+            endfunction
+            "
+        );
+        let formatted = indoc!(
+            "
+            function int f(a);
+                a = b;
+            endfunction
+            "
+        );
+        let edits = text_edits(original, formatted);
+
+        assert!(
+            edits
+                == vec![
+                    TextEdit {
+                        range: 19..21,
+                        replacement: "    ".to_string(),
+                    },
+                    TextEdit {
+                        range: 22..25,
+                        replacement: " ".to_string(),
+                    },
+                ]
+        );
+        assert_eq!(&apply(original, &edits), formatted);
+    }
+
+    #[test]
+    fn lines() {
+        init();
+
+        let original = indoc!(
+            "
+            function int f(a, b, c);
+                return f(a,
+                    b, c);
+            endfunction
+
+            function int g(a);
+            endfunction
+            "
+        );
+        let formatted = indoc!(
+            "
+            function int f(a, b, c);
+                return f(a, b, c);
+            endfunction
+
+            function int g(a);
+            endfunction
+            "
+        );
+        let edits = text_edits(original, formatted);
+
+        assert!(edits
+            .iter()
+            .all(|edit| edit.range.start > 25 && edit.range.end < 70));
+        assert_eq!(&apply(original, &edits), formatted);
+    }
+
+    #[test]
+    fn insert_and_delete() {
+        init();
+
+        let cases = [
+            ("", "a\n"),
+            ("a\n", ""),
+            ("a\nb\n", "a\nx\nb\n"),
+            ("a\nx\nb\n", "a\nb\n"),
+            ("a b c\n", "c b a\n"),
+            ("x", "y\nz"),
+        ];
+
+        for (original, formatted) in cases.iter() {
+            assert_eq!(
+                &apply(original, &text_edits(original, formatted)),
+                formatted
+            );
+        }
+    }
+
+    #[test]
+    fn many_changes() {
+        init();
+
+        let original = (0..2000)
+            .map(|i| format!("a{} = b{};\n", i, i % 7))
+            .collect::<String>();
+        let formatted = (0..2000)
+            .rev()
+            .map(|i| format!("a{} = b{};\n", i, i % 5))
+            .collect::<String>();
+
+        assert_eq!(
+            &apply(&original, &text_edits(&original, &formatted)),
+            &formatted
+        );
+    }
+}
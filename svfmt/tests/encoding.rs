@@ -0,0 +1,121 @@
+#[macro_use]
+mod common;
+
+mod encoding {
+    use crate::common::*;
+    use svfmt::{format_bytes, Config, Encoding};
+
+    #[test]
+    fn detect() {
+        init();
+
+        assert!(Encoding::detect("// Café\n".as_bytes()) == Encoding::Utf8);
+        assert!(Encoding::detect(b"// Caf\xe9\n") == Encoding::Latin1);
+    }
+
+    #[test]
+    fn round_trip() {
+        init();
+
+        let input = b"// Andr\xe9 M\xfcller\xa0\n";
+        let text = Encoding::Latin1.decode(input).unwrap();
+
+        assert_eq!(&text, "// André Müller\u{a0}\n");
+        assert!(Encoding::Latin1.encode(&text).unwrap().as_ref() == &input[..]);
+        assert!(Encoding::Latin1.encode("€").is_err());
+        assert!(Encoding::Utf8.decode(input).is_err());
+    }
+
+    #[test]
+    fn latin1_comment() {
+        init();
+
+        let input = b"// Auteur: Andr\xe9\nfunction int f(a);\n  return a;   // \xabvaleur\xbb\nendfunction\n";
+        let expected = b"// Auteur: Andr\xe9\nfunction int f(a);\n    return a; // \xabvaleur\xbb\nendfunction\n";
+        let mut output = Vec::new();
+        format_bytes(&mut output, input, &Config::default()).unwrap();
+
+        assert!(output[..] == expected[..]);
+    }
+
+    #[test]
+    fn declared_encoding() {
+        init();
+
+        let input = "// \u{e9}\nfunction int f(a);\nendfunction\n".as_bytes();
+        let config = Config::default().encoding(Some(Encoding::Latin1));
+        let mut output = Vec::new();
+        format_bytes(&mut output, input, &config).unwrap();
+
+        // Each byte of the UTF-8 sequence is kept as a Latin-1 character
+        assert!(output.starts_with("// \u{e9}\n".as_bytes()));
+    }
+}
+
+mod byte_order_mark {
+    use crate::common::*;
+    use svfmt::{decode_source, format_bytes, split_byte_order_mark, Config, Encoding, Session};
+
+    #[test]
+    fn split() {
+        init();
+
+        assert!(split_byte_order_mark(b"\xef\xbb\xbfmodule") == (true, &b"module"[..]));
+        assert!(split_byte_order_mark(b"module") == (false, &b"module"[..]));
+    }
+
+    #[test]
+    fn decode() {
+        init();
+
+        let (encoding, source) = decode_source(b"\xef\xbb\xbfmodule", &Config::default()).unwrap();
+
+        assert!(encoding == Encoding::Utf8);
+        assert_eq!(&source, "module");
+    }
+
+    #[test]
+    fn keep() {
+        init();
+
+        let input = b"\xef\xbb\xbffunction int f(a);\n  return a;\nendfunction\n";
+        let expected = b"\xef\xbb\xbffunction int f(a);\n    return a;\nendfunction\n";
+        let mut output = Vec::new();
+        format_bytes(&mut output, input, &Config::default()).unwrap();
+
+        assert!(output[..] == expected[..]);
+    }
+
+    #[test]
+    fn remove() {
+        init();
+
+        let input = b"\xef\xbb\xbffunction int f(a);\n  return a;\nendfunction\n";
+        let expected = b"function int f(a);\n    return a;\nendfunction\n";
+        let config = Config::default().keep_byte_order_mark(false);
+        let mut output = Vec::new();
+        format_bytes(&mut output, input, &config).unwrap();
+
+        assert!(output[..] == expected[..]);
+    }
+
+    #[test]
+    fn session_text() {
+        init();
+
+        let input = "\u{feff}function int f(a);\n  return a;\nendfunction\n";
+        let mut session = Session::new(Config::default()).unwrap();
+
+        assert_eq!(
+            &session.format(input).unwrap(),
+            "\u{feff}function int f(a);\n    return a;\nendfunction\n"
+        );
+
+        let mut session = Session::new(Config::default().keep_byte_order_mark(false)).unwrap();
+
+        assert_eq!(
+            &session.format(input).unwrap(),
+            "function int f(a);\n    return a;\nendfunction\n"
+        );
+    }
+}
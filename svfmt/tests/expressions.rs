@@ -26,4 +26,19 @@ mod expressions {
 
         assert_eq!(&transform(input), expected);
     }
+
+    #[test]
+    fn unary() {
+        init();
+
+        let input = indoc!(
+            "
+            function int f(a);
+                return -a;
+            endfunction
+            "
+        );
+
+        assert_eq!(&transform(input), input);
+    }
 }
@@ -112,4 +112,52 @@ mod functions {
 
         assert_eq!(&transform(input), expected);
     }
+
+    #[test]
+    fn wrap_arguments() {
+        init();
+
+        let input = indoc!(
+            "
+            function int f(int a);
+                return some_function_name(long_argument_name_a, long_argument_name_b, long_argument_name_c);
+            endfunction
+            "
+        );
+        let expected = indoc!(
+            "
+            function int f(int a);
+                return some_function_name(
+                    long_argument_name_a,
+                    long_argument_name_b,
+                    long_argument_name_c
+                );
+            endfunction
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn return_without_value() {
+        init();
+
+        let input = indoc!(
+            "
+            function void f(int a);
+                return ;
+            endfunction
+            "
+        );
+        let expected = indoc!(
+            "
+            function void f(int a);
+                return;
+            endfunction
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
 }
@@ -0,0 +1,68 @@
+#[macro_use]
+mod common;
+
+mod idempotency {
+    use crate::common::*;
+    use indoc::indoc;
+    use svfmt::{verify_idempotent, Config, Instability, Session};
+
+    #[test]
+    fn stable() {
+        init();
+
+        let input = indoc!(
+            "
+            function int f(a, b);
+                // Sum
+                return a + b; // Trailing
+            endfunction
+            "
+        );
+
+        assert!(verify_idempotent(input, &Config::default())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn session() {
+        init();
+
+        let input = indoc!(
+            "
+            function int f(a, b);
+                return a + b;  // Trailing
+            endfunction
+            "
+        );
+        let mut session = Session::new(Config::default().comment_spacing(2)).unwrap();
+
+        assert!(session.verify_idempotent(input).unwrap().is_none());
+    }
+
+    #[test]
+    fn diff() {
+        init();
+
+        let instability = Instability {
+            first_pass: "1\n2\n3\n4\n5\n6\n7\n8\n9\n".to_string(),
+            second_pass: "1\n2\n3\n4\n5a\n5b\n6\n7\n8\n9\n".to_string(),
+        };
+        let expected = indoc!(
+            "
+            @@ -2,7 +2,8 @@
+             2
+             3
+             4
+            -5
+            +5a
+            +5b
+             6
+             7
+             8
+            "
+        );
+
+        assert_eq!(&instability.to_string(), expected);
+    }
+}
@@ -0,0 +1,85 @@
+#[macro_use]
+mod common;
+
+mod instances {
+    use crate::common::*;
+    use indoc::indoc;
+    use svfmt::Config;
+
+    #[test]
+    fn named_connections() {
+        init();
+
+        let input = indoc!(
+            "
+            module top;
+            fifo #( .W(8) ) u_fifo(.clock( clock ), .data, .valid(), .*);
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module top;
+                fifo #(.W(8)) u_fifo (
+                    .clock(clock),
+                    .data,
+                    .valid(),
+                    .*
+                );
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn ordered_connections() {
+        init();
+
+        let input = indoc!(
+            "
+            module top;
+            inverter u_inverter(a,b);
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module top;
+                inverter u_inverter (a, b);
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn align_connections() {
+        init();
+
+        let input = indoc!(
+            "
+            module top;
+            fifo u_fifo(.clock(clock), .data_in(data), .valid(), .reset);
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module top;
+                fifo u_fifo (
+                    .clock   (clock),
+                    .data_in (data),
+                    .valid   (),
+                    .reset
+                );
+            endmodule
+            "
+        );
+        let config = Config::default().align_port_connections(true);
+
+        assert_eq!(&transform_with_config(input, &config), expected);
+    }
+}
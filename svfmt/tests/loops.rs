@@ -0,0 +1,119 @@
+#[macro_use]
+mod common;
+
+mod loops {
+    use crate::common::*;
+    use indoc::indoc;
+
+    #[test]
+    fn headers() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            initial begin
+            for(int i = 0;i < N;i++) begin
+            a[i] = 0;
+            end
+            foreach ( table[i,j] ) table[i][j] = 0;
+            while(busy) @(posedge clock);
+            repeat ( 4 ) @(posedge clock);
+            forever #5 clock = ~clock;
+            end
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                initial begin
+                    for (int i = 0; i < N; i++) begin
+                        a[i] = 0;
+                    end
+                    foreach (table[i, j])
+                        table[i][j] = 0;
+                    while (busy) @(posedge clock);
+                    repeat (4) @(posedge clock);
+                    forever #5
+                        clock = ~clock;
+                end
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn do_while() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            initial begin
+            do begin
+            count++;
+            end while(count < 10);
+            do count--; while (count > 0);
+            end
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                initial begin
+                    do begin
+                        count++;
+                    end while (count < 10);
+                    do
+                        count--;
+                    while (count > 0);
+                end
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn comments() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            initial begin
+            while (count < 10) // first
+            count++;
+            do begin
+            count--;
+            end
+            // second
+            while (count > 0);
+            end
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                initial begin
+                    while (count < 10) // first
+                        count++;
+                    do begin
+                        count--;
+                    end
+                    // second
+                    while (count > 0);
+                end
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+}
@@ -0,0 +1,216 @@
+#[macro_use]
+mod common;
+
+mod modules {
+    use crate::common::*;
+    use indoc::indoc;
+    use svfmt::Config;
+
+    #[test]
+    fn ports_fit() {
+        init();
+
+        let input = indoc!(
+            "
+            module m(input logic a,
+              output logic b);
+            assign b = a;
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m(input logic a, output logic b);
+                assign b = a;
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn ports_break() {
+        init();
+
+        let input = indoc!(
+            "
+            module long_module_name(input logic clock, input logic reset, output logic valid_out);
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module long_module_name(
+                input logic clock,
+                input logic reset,
+                output logic valid_out
+            );
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn items() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            logic a;
+
+
+            // Drives a
+            assign a = 1'b0;
+            endmodule : m
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                logic a;
+
+                // Drives a
+                assign a = 1'b0;
+            endmodule : m
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn align_ansi_ports() {
+        init();
+
+        let input = indoc!(
+            "
+            module m(input logic clock, input logic [7:0] data, output wire valid, output logic [3:0] mem [4]);
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m(
+                input  logic       clock,
+                input  logic [7:0] data,
+                output wire        valid,
+                output logic [3:0] mem   [4]
+            );
+            endmodule
+            "
+        );
+        let config = Config::default().align_port_declarations(true);
+
+        assert_eq!(&transform_with_config(input, &config), expected);
+    }
+
+    #[test]
+    fn align_non_ansi_ports() {
+        init();
+
+        let input = indoc!(
+            "
+            module m(clock, data, valid);
+            input clock;
+            input [7:0] data;
+            output reg valid;
+
+            wire w;
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m(clock, data, valid);
+                input            clock;
+                input      [7:0] data;
+                output reg       valid;
+
+                wire w;
+            endmodule
+            "
+        );
+        let config = Config::default().align_port_declarations(true);
+
+        assert_eq!(&transform_with_config(input, &config), expected);
+    }
+
+    #[test]
+    fn align_max_padding() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            input a;
+            input   [WIDTH_OF_THE_DATA_BUS - 1:0] b;
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                input a;
+                input [WIDTH_OF_THE_DATA_BUS - 1:0] b;
+            endmodule
+            "
+        );
+        let config = Config::default()
+            .align_port_declarations(true)
+            .max_alignment_padding(8);
+
+        assert_eq!(&transform_with_config(input, &config), expected);
+    }
+
+    #[test]
+    fn parameters_fit() {
+        init();
+
+        let input = indoc!(
+            "
+            module fifo #(parameter int   DEPTH=16, type  T = logic)(input T data);
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module fifo #(parameter int DEPTH = 16, type T = logic) (input T data);
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn parameters_break() {
+        init();
+
+        let input = indoc!(
+            "
+            interface bus #(parameter int ADDRESS_WIDTH = 32, parameter int DATA_WIDTH = 64, type T = logic);
+            endinterface
+            "
+        );
+        let expected = indoc!(
+            "
+            interface bus #(
+                parameter int ADDRESS_WIDTH = 32,
+                parameter int DATA_WIDTH    = 64,
+                type T                      = logic
+            );
+            endinterface
+            "
+        );
+        // `type T` is padded by more than the default limit
+        let config = Config::default()
+            .align_parameter_defaults(true)
+            .max_alignment_padding(24);
+
+        assert_eq!(&transform_with_config(input, &config), expected);
+    }
+}
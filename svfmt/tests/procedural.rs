@@ -0,0 +1,134 @@
+#[macro_use]
+mod common;
+
+mod procedural {
+    use crate::common::*;
+    use indoc::indoc;
+    use svfmt::{BeginStyle, Config};
+
+    #[test]
+    fn event_controls() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            always_ff @ ( posedge clock  or negedge reset_n ) q <= d;
+            always @( a,b ) x = a;
+            always @* y = b;
+            always @(*) z = c;
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                always_ff @(posedge clock or negedge reset_n)
+                    q <= d;
+                always @(a, b)
+                    x = a;
+                always @*
+                    y = b;
+                always @(*)
+                    z = c;
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn blocks() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            always_comb x = y;
+
+            initial   $display(\"start\");
+            final $display(\"done\");
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                always_comb
+                    x = y;
+
+                initial
+                    $display(\"start\");
+                final
+                    $display(\"done\");
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn begin_end() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            always_ff @(posedge clock)   begin:update
+            a <= b;
+
+
+            c <= d;
+            end:update
+            initial begin
+            end
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                always_ff @(posedge clock) begin : update
+                    a <= b;
+
+                    c <= d;
+                end : update
+                initial begin
+                end
+            endmodule
+            "
+        );
+
+        assert_eq!(&transform(input), expected);
+    }
+
+    #[test]
+    fn begin_on_own_line() {
+        init();
+
+        let input = indoc!(
+            "
+            module m;
+            always_comb begin
+            x = y;
+            end
+            endmodule
+            "
+        );
+        let expected = indoc!(
+            "
+            module m;
+                always_comb
+                begin
+                    x = y;
+                end
+            endmodule
+            "
+        );
+        let config = Config::default().begin_style(BeginStyle::OwnLine);
+
+        assert_eq!(&transform_with_config(input, &config), expected);
+    }
+}
@@ -0,0 +1,86 @@
+#[macro_use]
+mod common;
+
+mod rules {
+    use crate::common::*;
+    use indoc::indoc;
+    use svfmt::{missing_rule_kinds, tree_sitter_c, tree_sitter_verilog, Config, Doc, Session};
+
+    #[test]
+    fn builtin_rule_kinds() {
+        let missing = missing_rule_kinds(unsafe { tree_sitter_verilog() });
+
+        assert!(missing.is_empty(), "missing node kinds: {:?}", missing);
+    }
+
+    #[test]
+    fn missing_rule_kinds_of_other_languages() {
+        init();
+
+        let session =
+            Session::with_language(unsafe { tree_sitter_c() }, Config::default()).unwrap();
+
+        assert!(session.missing_rule_kinds().contains(&"module_declaration"));
+        assert!(Session::new(Config::default())
+            .unwrap()
+            .missing_rule_kinds()
+            .is_empty());
+    }
+
+    #[test]
+    fn override_builtin() {
+        init();
+
+        let input = indoc!(
+            "
+            function int f(a);
+                a = 'hff;
+            endfunction
+            "
+        );
+        let expected = indoc!(
+            "
+            function int f(a);
+                a = 'HFF;
+            endfunction
+            "
+        );
+        let mut config = Config::default();
+        config.rules.register("primary_literal", |node, cx| {
+            Ok(Doc::text(cx.text(node).to_uppercase()))
+        });
+
+        assert_eq!(&transform_with_config(input, &config), expected);
+    }
+
+    #[test]
+    fn format_child() {
+        init();
+
+        let input = indoc!(
+            "
+            function int f(int a, int b);
+                return a+b;
+            endfunction
+            "
+        );
+        let expected = indoc!(
+            "
+            function int f(int a, int b);
+                return (a + b);
+            endfunction
+            "
+        );
+        let mut config = Config::default();
+        config.rules.register("jump_statement", |node, cx| {
+            let expression = node.child(1).unwrap();
+            Ok(Doc::concat(vec![
+                Doc::text("return ("),
+                cx.format_child(expression)?,
+                Doc::text(")"),
+            ]))
+        });
+
+        assert_eq!(&transform_with_config(input, &config), expected);
+    }
+}
@@ -0,0 +1,117 @@
+#[macro_use]
+mod common;
+
+mod session {
+    use crate::common::*;
+    use indoc::indoc;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::Duration;
+    use svfmt::{Config, Error, Session};
+
+    #[test]
+    fn send() {
+        fn assert_send<T: Send>() {}
+
+        assert_send::<Session>();
+    }
+
+    #[test]
+    fn reuse() {
+        init();
+
+        let input = indoc!(
+            "
+            function int f(a);
+              return a;
+            endfunction
+            "
+        );
+        let expected = indoc!(
+            "
+            function int f(a);
+                return a;
+            endfunction
+            "
+        );
+        let mut session = Session::new(Config::default()).unwrap();
+
+        assert_eq!(&session.format(input).unwrap(), expected);
+        assert_eq!(&session.format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn writer() {
+        init();
+
+        let input = b"// Auteur: Andr\xe9\nfunction int f(a);\n  return a;\nendfunction\n";
+        let expected = b"// Auteur: Andr\xe9\nfunction int f(a);\n    return a;\nendfunction\n";
+        let mut session = Session::new(Config::default()).unwrap();
+        let mut output = Vec::new();
+        session.format_bytes_to(&mut output, input).unwrap();
+
+        assert!(output[..] == expected[..]);
+
+        let mut output = Vec::new();
+        session
+            .format_to(&mut output, "function int f(a);\nendfunction\n")
+            .unwrap();
+
+        assert!(output[..] == b"function int f(a);\nendfunction\n"[..]);
+    }
+
+    #[test]
+    fn cancel() {
+        init();
+
+        let mut session = Session::new(Config::default()).unwrap();
+        let flag = session.cancellation_flag();
+
+        flag.store(1, Ordering::SeqCst);
+        match session.format("function int f(a);\nendfunction\n") {
+            Err(Error::ParseCancelled) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        flag.store(0, Ordering::SeqCst);
+        assert!(session.format("function int f(a);\nendfunction\n").is_ok());
+    }
+
+    #[test]
+    fn cancel_while_parsing() {
+        init();
+
+        let source = "function int f(a);\n    return a;\nendfunction\n".repeat(100_000);
+        let mut session = Session::new(Config::default()).unwrap();
+        let flag = session.cancellation_flag();
+
+        // The session may have moved to another thread since the flag was taken
+        let cancel = thread::spawn(move || flag.store(1, Ordering::SeqCst));
+        let result = thread::spawn(move || session.format(&source).map(|_| ()))
+            .join()
+            .unwrap();
+        cancel.join().unwrap();
+
+        match result {
+            Err(Error::ParseCancelled) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn timeout() {
+        init();
+
+        let source = "function int f(a);\n    return a;\nendfunction\n".repeat(10000);
+        let mut session = Session::new(Config::default()).unwrap();
+
+        session.set_timeout(Some(Duration::from_micros(1)));
+        match session.format(&source) {
+            Err(Error::ParseCancelled) => {}
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+
+        session.set_timeout(None);
+        assert!(session.format(&source).is_ok());
+    }
+}
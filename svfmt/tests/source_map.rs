@@ -0,0 +1,95 @@
+#[macro_use]
+mod common;
+
+mod source_map {
+    use crate::common::*;
+    use indoc::indoc;
+    use svfmt::{format_with_source_map, parse, tree_sitter_verilog, Config, SourceMap};
+
+    #[test]
+    fn to_output() {
+        init();
+
+        let input = indoc!(
+            "
+            function int f(a);
+              a   =   b;
+            endfunction
+            "
+        );
+        let expected = indoc!(
+            "
+            function int f(a);
+                a = b;
+            endfunction
+            "
+        );
+
+        let tree = parse(unsafe { tree_sitter_verilog() }, input).unwrap();
+        let mut output = Vec::new();
+        let map = format_with_source_map(&mut output, input, &tree, &Config::default()).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(&output, expected);
+
+        // Within a token
+        let a = input.find("a   =").unwrap();
+        let b = input.find("b;").unwrap();
+        assert!(map.to_output(a) == output.find("a = b").unwrap());
+        assert!(map.to_output(b) == output.find("b;").unwrap());
+
+        // Between tokens maps to the end of the preceding token
+        assert!(map.to_output(a + 2) == output.find(" = b").unwrap());
+
+        // Back to the source
+        assert!(map.to_source(output.find("b;").unwrap()) == b);
+    }
+
+    fn format(input: &str) -> (SourceMap, String) {
+        let tree = parse(unsafe { tree_sitter_verilog() }, input).unwrap();
+        let mut output = Vec::new();
+        let map = format_with_source_map(&mut output, input, &tree, &Config::default()).unwrap();
+        (map, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn block_comment() {
+        init();
+
+        let input = indoc!(
+            "
+            function int f(a);
+                  /* First
+                     second */
+                  a = b;
+            endfunction
+            "
+        );
+        let (map, output) = format(input);
+
+        for text in &["First", "second", "*/"] {
+            let source = input.find(text).unwrap();
+            let formatted = output.find(text).unwrap();
+            assert!(map.to_output(source) == formatted);
+            assert!(map.to_source(formatted) == source);
+        }
+    }
+
+    #[test]
+    fn end_of_line_comment() {
+        init();
+
+        let input = indoc!(
+            "
+            function int f(a);
+              a   =   b;   // Note
+            endfunction
+            "
+        );
+        let (map, output) = format(input);
+
+        let source = input.find("Note").unwrap();
+        let formatted = output.find("Note").unwrap();
+        assert!(map.to_output(source) == formatted);
+        assert!(map.to_source(formatted) == source);
+    }
+}